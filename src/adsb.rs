@@ -1,9 +1,12 @@
-use crate::data;
+use crate::{data, emergency};
+use std::collections::HashSet;
 use std::io::{BufRead, BufReader};
 use std::net::TcpStream;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
 use std::time::{Duration, SystemTime};
 
+mod modes;
+
 pub enum ConnectionState {
     Disconnected,
     Connecting,
@@ -14,16 +17,51 @@ pub struct Channels {
     pub connect_rx: mpsc::Receiver<String>,
 
     pub plot_tx: mpsc::Sender<data::Point>,
+    // Times of Mode A/C replies that were not valid Gillham coded altitudes
+    pub invalid_tx: mpsc::Sender<SystemTime>,
+    pub emergency_tx: mpsc::Sender<emergency::Reply>,
+    pub mode_s_tx: mpsc::Sender<Report>,
     pub connection_state_tx: mpsc::Sender<ConnectionState>,
 }
 
+/// Something decoded from a Mode S reply or extended squitter. Only reports from addresses that
+/// have been checked by the CRC are sent.
+pub struct Report {
+    pub icao: u32,
+    // Whether the address was checked by the CRC, rather than recovered from the parity
+    pub verified: bool,
+    pub time: SystemTime,
+    pub kind: ReportKind,
+}
+
+pub enum ReportKind {
+    // Barometric, in feet
+    Altitude(u32),
+    // Height above the WGS84 ellipsoid, in feet
+    GnssHeight(u32),
+    // GNSS height minus barometric altitude, in feet
+    GnssDifference(i32),
+    Callsign(String),
+    // Only the address, so it is known to be real
+    Seen,
+}
+
 struct Adsb {
     channels: Channels,
+    // The receiver currently connected to
+    source: Option<Arc<str>>,
+    // Addresses seen in replies the CRC could check, so addresses recovered from the parity of
+    // other replies can be trusted
+    known: HashSet<u32>,
 }
 
 impl Adsb {
     fn new(channels: Channels) -> Self {
-        Adsb { channels }
+        Adsb {
+            channels,
+            source: None,
+            known: HashSet::new(),
+        }
     }
 
     fn run(&mut self) {
@@ -32,7 +70,8 @@ impl Adsb {
             let addr = self.channels.connect_rx.recv().unwrap();
             self.set_connection_state(ConnectionState::Connecting);
 
-            if let Ok(stream) = TcpStream::connect(addr) {
+            if let Ok(stream) = TcpStream::connect(&addr) {
+                self.source = Some(addr.into());
                 stream
                     .set_read_timeout(Option::from(Duration::from_secs(30)))
                     .unwrap();
@@ -53,11 +92,13 @@ impl Adsb {
 
             let result = reader.read_line(&mut message);
             match result {
+                // The server closed the connection
+                Ok(0) => return,
                 Ok(_) => {
                     self.on_message(&message);
                 }
                 Err(e) => {
-                    println!("error reading: {}", e);
+                    eprintln!("Error reading from server: {e}");
                     return;
                 }
             }
@@ -67,26 +108,90 @@ impl Adsb {
         // thread::sleep(time::Duration::from_millis(25));
     }
 
-    //*5124;
+    //*5124; for Mode A/C or *8D4840D6202CC371C32CE0576098; for Mode S
     fn on_message(&mut self, message: &str) {
-        if message.len() != 7 {
-            // Only want mode A/C messages
+        let frame = match message.trim().strip_prefix('*') {
+            Some(frame) => frame.strip_suffix(';').unwrap_or(frame),
+            None => return,
+        };
+        if !frame.is_ascii() {
+            return;
+        }
+
+        match frame.len() {
+            4 => self.on_mode_ac(frame),
+            14 | 28 => self.on_mode_s(frame),
+            _ => {}
+        }
+    }
+
+    fn on_mode_s(&mut self, frame: &str) {
+        let bytes: Result<Vec<u8>, _> = (0..frame.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&frame[i..i + 2], 16))
+            .collect();
+
+        let report = match bytes
+            .ok()
+            .and_then(|bytes| modes::decode(&bytes, SystemTime::now()))
+        {
+            Some(report) => report,
+            None => return,
+        };
+
+        // Otherwise every garbled reply would add a new aircraft
+        if report.verified {
+            self.known.insert(report.icao);
+        } else if !self.known.contains(&report.icao) {
             return;
         }
 
-        if let Ok(i) = u32::from_str_radix(&message[1..5], 16) {
-            if let Ok(alt) = mode_a_to_mode_c(i) {
-                self.add_point(alt * 100);
+        if let ReportKind::Altitude(altitude) = report.kind {
+            self.add_point(altitude, data::Message::ModeS { icao: report.icao });
+        }
+        self.channels
+            .mode_s_tx
+            .send(report)
+            .expect("Failed to send Mode S report");
+    }
+
+    fn on_mode_ac(&mut self, frame: &str) {
+        if let Ok(code) = u16::from_str_radix(frame, 16) {
+            // Emergency codes are never valid altitudes, so these can only be identity replies
+            let squawk = mode_a_squawk(code);
+            if emergency::SQUAWKS.contains(&squawk) {
+                self.channels
+                    .emergency_tx
+                    .send(emergency::Reply {
+                        squawk,
+                        time: SystemTime::now(),
+                    })
+                    .expect("Failed to send emergency");
+                return;
+            }
+
+            match mode_a_to_mode_c(u32::from(code)) {
+                Ok(alt) => self.add_point(alt * 100, data::Message::ModeAc { code }),
+                Err(_) => self
+                    .channels
+                    .invalid_tx
+                    .send(SystemTime::now())
+                    .expect("Failed to send invalid reply"),
             }
         }
     }
 
-    fn add_point(&mut self, height: u32) {
+    fn add_point(&mut self, height: u32, message: data::Message) {
         let now = SystemTime::now();
 
         self.channels
             .plot_tx
-            .send(data::Point { height, time: now })
+            .send(data::Point {
+                height,
+                time: now,
+                message,
+                source: self.source.clone(),
+            })
             .expect("Failed to send plot");
     }
 
@@ -103,6 +208,13 @@ pub fn run(channels: Channels) {
     adsb.run();
 }
 
+// The code read as a Mode A identity, each hex digit holds one octal digit of the squawk
+fn mode_a_squawk(code: u16) -> u16 {
+    (0..4).fold(0, |squawk, digit| {
+        squawk * 10 + ((code >> (12 - digit * 4)) & 0x7)
+    })
+}
+
 // Taken from: https://github.com/rsadsb/adsb_deku/blob/c9944134ef5816f1f2151d8a7ac7f5556f213e91/libadsb_deku/src/mode_ac.rs#L53 under the MIT license
 fn mode_a_to_mode_c(mode_a: u32) -> Result<u32, &'static str> {
     let mut five_hundreds: u32 = 0;
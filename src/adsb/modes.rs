@@ -0,0 +1,282 @@
+use super::{mode_a_to_mode_c, Report, ReportKind};
+use std::time::SystemTime;
+
+// Mode S CRC generator polynomial
+const CRC_POLYNOMIAL: u32 = 0x1ff_f409;
+const FEET_PER_METRE: f64 = 3.280_84;
+const CALLSIGN_CHARS: &[u8; 64] =
+    b"#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######";
+
+/// Decodes the altitude, GNSS height or callsign from a Mode S frame, if it has one.
+pub fn decode(frame: &[u8], time: SystemTime) -> Option<Report> {
+    let parity_start = frame.len().checked_sub(3)?;
+    let parity = u32::from(frame[parity_start]) << 16
+        | u32::from(frame[parity_start + 1]) << 8
+        | u32::from(frame[parity_start + 2]);
+    let crc = crc(&frame[..parity_start]);
+    let df = frame[0] >> 3;
+
+    // Extended squitters and all call replies carry the address in the clear and can be
+    // checked, the other replies have it overlaid on the parity so any error gives a bad address
+    let (icao, verified) = match df {
+        11 | 17 | 18 => (address(frame), true),
+        _ => (crc ^ parity, false),
+    };
+    let valid = match df {
+        17 | 18 => crc == parity,
+        // The interrogator identifier is overlaid on the low bits
+        11 => (crc ^ parity) & !0x7f == 0,
+        _ => true,
+    };
+    if !valid {
+        return None;
+    }
+
+    let kind = match (df, frame.len()) {
+        (0 | 4 | 16 | 20, _) => {
+            let ac = u16::from(frame[2] & 0x1f) << 8 | u16::from(frame[3]);
+            ReportKind::Altitude(ac13_altitude(ac)?)
+        }
+        (17 | 18, 14) => match frame[4] >> 3 {
+            1..=4 => ReportKind::Callsign(callsign(&frame[5..11])),
+            9..=18 => {
+                let ac = u16::from(frame[5]) << 4 | u16::from(frame[6] >> 4);
+                // Squitters leave out the M bit, which is always 0 for them
+                ReportKind::Altitude(ac13_altitude((ac & 0xfc0) << 1 | (ac & 0x3f))?)
+            }
+            // Airborne velocity, which also gives how far the GNSS height is above the
+            // barometric altitude
+            19 => match (frame[4] & 0x7, frame[10] & 0x7f) {
+                (1..=4, 1..=126) => {
+                    let difference = (i32::from(frame[10] & 0x7f) - 1) * 25;
+                    ReportKind::GnssDifference(if frame[10] & 0x80 != 0 {
+                        -difference
+                    } else {
+                        difference
+                    })
+                }
+                _ => ReportKind::Seen,
+            },
+            20..=22 => {
+                // Plain binary height above the ellipsoid, in metres
+                let ac = u16::from(frame[5]) << 4 | u16::from(frame[6] >> 4);
+                if ac == 0 {
+                    return None;
+                }
+                ReportKind::GnssHeight((f64::from(ac) * FEET_PER_METRE).round() as u32)
+            }
+            _ => ReportKind::Seen,
+        },
+        (11, _) => ReportKind::Seen,
+        _ => return None,
+    };
+
+    Some(Report {
+        icao,
+        verified,
+        time,
+        kind,
+    })
+}
+
+fn address(frame: &[u8]) -> u32 {
+    u32::from(frame[1]) << 16 | u32::from(frame[2]) << 8 | u32::from(frame[3])
+}
+
+fn crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for byte in data {
+        crc ^= u32::from(*byte) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= CRC_POLYNOMIAL;
+            }
+        }
+    }
+
+    crc & 0xff_ffff
+}
+
+// 13 bit altitude code: C1 A1 C2 A2 C4 A4 M B1 Q B2 D2 B4 D4
+fn ac13_altitude(ac: u16) -> Option<u32> {
+    // Metric altitudes are not used
+    if ac == 0 || ac & 0x40 != 0 {
+        return None;
+    }
+
+    if ac & 0x10 != 0 {
+        // 25 ft increments
+        let n = u32::from((ac & 0x1f80) >> 2 | (ac & 0x20) >> 1 | (ac & 0xf));
+        return (n * 25).checked_sub(1000);
+    }
+
+    // Otherwise it's Gillham coded like Mode C, so rearrange the bits to match a Mode A/C reply
+    let bits = [
+        (12, 0x0010), // C1
+        (11, 0x1000), // A1
+        (10, 0x0020), // C2
+        (9, 0x2000),  // A2
+        (8, 0x0040),  // C4
+        (7, 0x4000),  // A4
+        (5, 0x0100),  // B1
+        (3, 0x0200),  // B2
+        (2, 0x0002),  // D2
+        (1, 0x0400),  // B4
+        (0, 0x0004),  // D4
+    ];
+    let code = bits
+        .iter()
+        .filter(|(bit, _)| ac & (1 << bit) != 0)
+        .fold(0, |code, (_, mode_a)| code | mode_a);
+
+    mode_a_to_mode_c(code).ok().map(|altitude| altitude * 100)
+}
+
+fn callsign(data: &[u8]) -> String {
+    let bits = data
+        .iter()
+        .fold(0u64, |bits, byte| bits << 8 | u64::from(*byte));
+
+    (0..8)
+        .map(|i| CALLSIGN_CHARS[((bits >> (42 - i * 6)) & 0x3f) as usize] as char)
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn frame(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // Fills in the parity of a made up extended squitter
+    fn with_parity(mut frame: Vec<u8>) -> Vec<u8> {
+        let crc = crc(&frame[..11]);
+        frame.extend_from_slice(&crc.to_be_bytes()[1..]);
+        frame
+    }
+
+    fn decode_hex(hex: &str) -> Option<Report> {
+        decode(&frame(hex), UNIX_EPOCH)
+    }
+
+    // Frames from "The 1090 Megahertz Riddle" and the pyModeS tests
+    #[test]
+    fn crc_matches_published_squitters() {
+        for hex in [
+            "8D4840D6202CC371C32CE0576098",
+            "8D40621D58C382D690C8AC2863A7",
+            "8D485020994409940838175B284F",
+            "8DA05F219B06B6AF189400CBC33F",
+        ] {
+            let frame = frame(hex);
+            let parity = frame[11..]
+                .iter()
+                .fold(0, |parity, byte| parity << 8 | u32::from(*byte));
+            assert_eq!(crc(&frame[..11]), parity, "{hex}");
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_squitters() {
+        let mut corrupted = frame("8D4840D6202CC371C32CE0576098");
+        corrupted[6] ^= 0x10;
+        assert!(decode(&corrupted, UNIX_EPOCH).is_none());
+    }
+
+    #[test]
+    fn callsign() {
+        let report = decode_hex("8D4840D6202CC371C32CE0576098").unwrap();
+        assert_eq!(report.icao, 0x4840D6);
+        assert!(report.verified);
+        assert!(matches!(report.kind, ReportKind::Callsign(callsign) if callsign == "KLM1023"));
+    }
+
+    #[test]
+    fn airborne_position_altitude() {
+        let report = decode_hex("8D40621D58C382D690C8AC2863A7").unwrap();
+        assert_eq!(report.icao, 0x40621D);
+        assert!(matches!(report.kind, ReportKind::Altitude(38_000)));
+    }
+
+    #[test]
+    fn surveillance_reply_altitude() {
+        // The address is recovered from the parity, so can't be checked
+        let report = decode_hex("A02014B400000000000000F9D514").unwrap();
+        assert!(!report.verified);
+        assert!(matches!(report.kind, ReportKind::Altitude(32_300)));
+    }
+
+    #[test]
+    fn gillham_altitudes() {
+        // D2 D4 A1 A2 A4 B1 B2 B4 C1 C2 C4, then where each sits in the 13 bit altitude code
+        const POSITIONS: [u16; 11] = [2, 0, 11, 9, 7, 5, 3, 1, 12, 10, 8];
+        for (gray, altitude) in [
+            ("00000011010", 0),
+            ("00000011110", 100),
+            ("00000010011", 600),
+            ("00000110010", 1000),
+            ("00001001001", 5800),
+            ("00011100100", 10_300),
+            ("01100011010", 32_000),
+            ("01110000100", 46_300),
+            ("01010101100", 50_200),
+            ("11011110100", 73_200),
+            ("10000000011", 126_600),
+        ] {
+            let ac = gray
+                .chars()
+                .zip(POSITIONS)
+                .filter(|(bit, _)| *bit == '1')
+                .fold(0, |ac, (_, position)| ac | 1 << position);
+            assert_eq!(ac13_altitude(ac), Some(altitude), "{gray}");
+        }
+    }
+
+    #[test]
+    fn metric_altitudes_are_ignored() {
+        assert_eq!(ac13_altitude(0x14B4 | 0x40), None);
+        assert_eq!(ac13_altitude(0), None);
+    }
+
+    #[test]
+    fn gnss_difference() {
+        let report = decode_hex("8D485020994409940838175B284F").unwrap();
+        assert!(matches!(report.kind, ReportKind::GnssDifference(550)));
+
+        // Airspeed rather than ground speed, and no difference available
+        let report = decode_hex("8DA05F219B06B6AF189400CBC33F").unwrap();
+        assert!(matches!(report.kind, ReportKind::Seen));
+
+        let mut below = frame("8D485020994409940838175B284F");
+        below[10] = 0x80 | 5;
+        let report = decode(&with_parity(below[..11].to_vec()), UNIX_EPOCH).unwrap();
+        assert!(matches!(report.kind, ReportKind::GnssDifference(-100)));
+    }
+
+    #[test]
+    fn gnss_height() {
+        // Type code 20 with a height of 3,000m
+        let height: u16 = 3000;
+        let mut squitter = frame("8D4840D6");
+        squitter.extend_from_slice(&[
+            20 << 3,
+            (height >> 4) as u8,
+            ((height & 0xf) << 4) as u8,
+            0,
+            0,
+            0,
+            0,
+        ]);
+        let report = decode(&with_parity(squitter), UNIX_EPOCH).unwrap();
+        assert!(matches!(report.kind, ReportKind::GnssHeight(9843)));
+    }
+}
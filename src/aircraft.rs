@@ -0,0 +1,147 @@
+use crate::{correlate, database};
+use eframe::egui;
+use eframe::egui::ecolor::Hsva;
+use eframe::egui::Color32;
+use std::collections::{BTreeSet, HashMap};
+
+// Replies without an address can't be told apart, so they share a colour
+pub const MODE_AC_COLOR: Color32 = Color32::from_rgb(100, 200, 100);
+// Other aircraft fade into the background while any are highlighted
+const DIMMED: f32 = 0.25;
+
+/// How an aircraft's points are drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Display {
+    #[default]
+    Shown,
+    Hidden,
+    Highlighted,
+}
+
+/// Which aircraft are shown, hidden or highlighted. Replies without an address are `None`, and
+/// are picked like one more aircraft.
+#[derive(Default)]
+pub struct Selection {
+    pub open: bool,
+    displays: HashMap<Option<u32>, Display>,
+    // Aircraft with points in the visible window last frame, so the list only offers those
+    in_view: BTreeSet<Option<u32>>,
+}
+
+impl Selection {
+    pub fn display(&self, aircraft: Option<u32>) -> Display {
+        self.displays.get(&aircraft).copied().unwrap_or_default()
+    }
+
+    /// The colour to draw an aircraft's points with, taking highlighting into account.
+    pub fn color(&self, aircraft: Option<u32>) -> Color32 {
+        let color = color(aircraft);
+        let highlighting = self
+            .displays
+            .values()
+            .any(|display| *display == Display::Highlighted);
+
+        if highlighting && self.display(aircraft) != Display::Highlighted {
+            color.linear_multiply(DIMMED)
+        } else {
+            color
+        }
+    }
+
+    pub fn set_in_view(&mut self, in_view: BTreeSet<Option<u32>>) {
+        self.in_view = in_view;
+    }
+
+    pub fn ui(
+        &mut self,
+        ctx: &egui::Context,
+        correlator: &correlate::Correlator,
+        database: &database::Database,
+    ) {
+        // Aircraft that have been picked stay in the list after they leave the view, so they can
+        // be put back
+        let listed: BTreeSet<Option<u32>> = self
+            .in_view
+            .iter()
+            .copied()
+            .chain(
+                self.displays
+                    .iter()
+                    .filter(|(_, display)| **display != Display::Shown)
+                    .map(|(aircraft, _)| *aircraft),
+            )
+            .collect();
+        let displays = &mut self.displays;
+
+        egui::Window::new("Aircraft")
+            .open(&mut self.open)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                if ui.button("Show all").clicked() {
+                    displays.clear();
+                }
+
+                egui::Grid::new("Aircraft list")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for aircraft in listed {
+                            let display = displays.entry(aircraft).or_default();
+                            ui.colored_label(color(aircraft), "⏺");
+                            ui.label(name(aircraft, correlator, database));
+                            ui.selectable_value(display, Display::Shown, "Show");
+                            ui.selectable_value(display, Display::Hidden, "Hide");
+                            ui.selectable_value(display, Display::Highlighted, "Highlight");
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        self.displays
+            .retain(|_, display| *display != Display::Shown);
+    }
+}
+
+/// Each aircraft's own colour, spread around the hue circle by its address so it stays the same
+/// between sessions.
+pub fn color(aircraft: Option<u32>) -> Color32 {
+    match aircraft {
+        Some(icao) => {
+            // Fibonacci hashing spreads neighbouring addresses apart, and the top 24 bits fit
+            // in an f32 exactly
+            let hue = (icao.wrapping_mul(0x9E37_79B9) >> 8) as f32 / (1 << 24) as f32;
+            Hsva::new(hue, 0.6, 0.95, 1.0).into()
+        }
+        None => MODE_AC_COLOR,
+    }
+}
+
+/// The address and callsign, followed by the registration and type when the database has them.
+pub fn label(icao: u32, callsign: Option<&str>, entry: Option<&database::Entry>) -> String {
+    let mut label = format!("{:06X}", icao);
+    if let Some(callsign) = callsign {
+        label += &format!(" {:}", callsign);
+    }
+    if let Some(short) = entry.map(database::Entry::short).filter(|s| !s.is_empty()) {
+        label += &format!(" {:}", short);
+    }
+    label
+}
+
+/// The label along with the operator, for the legend and the aircraft list.
+pub fn name(
+    aircraft: Option<u32>,
+    correlator: &correlate::Correlator,
+    database: &database::Database,
+) -> String {
+    let icao = match aircraft {
+        Some(icao) => icao,
+        None => return "Mode A/C".to_owned(),
+    };
+
+    let entry = database.get(icao);
+    let label = label(icao, correlator.callsign(icao), entry);
+    match entry.filter(|entry| !entry.operator.is_empty()) {
+        Some(entry) => format!("{:} ({:})", label, entry.operator),
+        None => label,
+    }
+}
@@ -0,0 +1,495 @@
+use crate::{data, store, units, view};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const LOG_FILE_NAME: &str = "alerts.log";
+// How many fired alerts are kept to show on screen
+const MAX_HISTORY: usize = 100;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Fires when at least `count` Mode A/C points above `min_height` arrive within `window_secs`.
+/// Mode S altitudes come from identified aircraft and are left out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Rule {
+    pub name: String,
+    pub enabled: bool,
+    pub min_height: u32,
+    pub count: u32,
+    pub window_secs: u32,
+    // Only count points kept by the applied denoise filter, which delays the alert until they have
+    // been classified. The rule is paused while no filter is applied.
+    pub after_denoise: bool,
+    // Run through the shell when the rule fires, left empty to do nothing
+    pub command: String,
+    // An http:// URL on this machine or the local network to POST the alert to as JSON, left
+    // empty to do nothing
+    pub webhook: String,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            name: "High flyer".to_owned(),
+            enabled: true,
+            min_height: 55_000,
+            count: 3,
+            window_secs: 60,
+            after_denoise: true,
+            command: String::new(),
+            webhook: String::new(),
+        }
+    }
+}
+
+impl Rule {
+    fn ui(&mut self, ui: &mut egui::Ui, converter: units::Converter, denoise_applied: bool) {
+        ui.horizontal(|ui| {
+            ui.label("Name: ");
+            ui.text_edit_singleline(&mut self.name);
+        });
+        ui.checkbox(&mut self.enabled, "Enabled");
+        ui.add(
+            egui::Slider::new(&mut self.min_height, 0..=100_000)
+                .custom_formatter(|x, _| converter.format(x))
+                .custom_parser(|text| converter.parse(text))
+                .text("Above"),
+        );
+        ui.add(egui::Slider::new(&mut self.count, 1..=100).text("Points"));
+        ui.add(
+            egui::Slider::new(&mut self.window_secs, 1..=600)
+                .custom_formatter(|x, _| format!("{:.0} s", x))
+                .text("Within"),
+        );
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.after_denoise, "After denoise");
+            if self.paused(denoise_applied) {
+                ui.colored_label(
+                    egui::Color32::from_rgb(255, 140, 60),
+                    "Paused until denoise is applied",
+                );
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Command: ");
+            ui.text_edit_singleline(&mut self.command);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Webhook: ");
+            ui.text_edit_singleline(&mut self.webhook).on_hover_text(
+                "e.g. http://localhost:8080/alert, only addresses on this machine or the local \
+                 network are allowed",
+            );
+        });
+    }
+
+    fn paused(&self, denoise_applied: bool) -> bool {
+        self.after_denoise && !denoise_applied
+    }
+
+    fn describe(&self, converter: units::Converter) -> String {
+        format!(
+            "{:} points above {:} within {:}s",
+            self.count,
+            converter.format(f64::from(self.min_height)),
+            self.window_secs
+        )
+    }
+}
+
+pub fn rules_ui(
+    ui: &mut egui::Ui,
+    rules: &mut Vec<Rule>,
+    converter: units::Converter,
+    denoise_applied: bool,
+) {
+    let mut remove = None;
+    for (index, rule) in rules.iter_mut().enumerate() {
+        let mut title = format!("{:} ({:})", rule.name, rule.describe(converter));
+        if rule.paused(denoise_applied) {
+            title += " - paused";
+        }
+        egui::CollapsingHeader::new(title)
+            .id_source(("Alert rule", index))
+            .show(ui, |ui| {
+                rule.ui(ui, converter, denoise_applied);
+                if ui.button("Delete rule").clicked() {
+                    remove = Some(index);
+                }
+            });
+    }
+    if let Some(index) = remove {
+        rules.remove(index);
+    }
+
+    if ui.button("Add rule").clicked() {
+        rules.push(Rule::default());
+    }
+}
+
+struct Alert {
+    rule: String,
+    message: String,
+}
+
+// Matching points seen by a rule, it fires once the window holds enough of them and can only
+// fire again once the window has emptied out
+#[derive(Default)]
+struct RuleState {
+    checked_until: Option<SystemTime>,
+    matches: VecDeque<SystemTime>,
+    fired: bool,
+}
+
+/// Evaluates the alert rules against live points as they arrive.
+#[derive(Default)]
+pub struct Alerts {
+    // The rules the state was built for, so edits start them again
+    rules: Vec<Rule>,
+    states: Vec<RuleState>,
+    store_generation: u64,
+    history: VecDeque<Alert>,
+    unseen: usize,
+}
+
+impl Alerts {
+    /// Checks any new points. `keep` is the applied denoise filter, or `None` when none is applied
+    /// and rules that count denoised points are paused. Points arriving while a rule is paused are
+    /// never checked by it.
+    pub fn update(
+        &mut self,
+        rules: &[Rule],
+        store: &store::Store,
+        keep: Option<&[bool]>,
+        log_file: &str,
+        utc: bool,
+        converter: units::Converter,
+    ) {
+        let points = store.as_slice();
+
+        // Loaded and autosaved points were never live, so rules carry on from where they were
+        // and skip past them. Edited rules only look at points from now on so they don't fire
+        // again for what has already been seen.
+        if self.store_generation != store.generation() {
+            self.store_generation = store.generation();
+            for state in &mut self.states {
+                state.checked_until = state.checked_until.max(store.newest_extended());
+            }
+        }
+        if self.rules != rules {
+            self.rules = rules.to_vec();
+            let checked_until = points.last().map(|point| point.time);
+            self.states = rules
+                .iter()
+                .map(|_| RuleState {
+                    checked_until,
+                    ..RuleState::default()
+                })
+                .collect();
+        }
+        let mut fired = vec![];
+
+        for (rule, state) in self.rules.iter().zip(&mut self.states) {
+            if !rule.enabled {
+                continue;
+            }
+            if rule.paused(keep.is_some()) {
+                state.checked_until = points.last().map(|point| point.time);
+                state.matches.clear();
+                continue;
+            }
+
+            let from = state.checked_until.map_or(0, |until| {
+                points.partition_point(|point| point.time <= until)
+            });
+            let keep = keep.filter(|_| rule.after_denoise);
+            let to = keep.map_or(points.len(), |keep| keep.len().min(points.len()));
+            let window = Duration::from_secs(u64::from(rule.window_secs));
+
+            for (index, point) in points.iter().enumerate().take(to).skip(from) {
+                let kept = keep.map_or(true, |keep| keep[index]);
+                if !kept || point.message.icao().is_some() || point.height <= rule.min_height {
+                    continue;
+                }
+
+                state.matches.push_back(point.time);
+                while state
+                    .matches
+                    .front()
+                    .map_or(false, |time| *time + window < point.time)
+                {
+                    state.matches.pop_front();
+                }
+
+                if state.matches.len() == 1 {
+                    state.fired = false;
+                }
+                if !state.fired && state.matches.len() >= rule.count as usize {
+                    state.fired = true;
+                    fired.push((rule.clone(), point.clone()));
+                }
+            }
+
+            if to > from {
+                state.checked_until = Some(points[to - 1].time);
+            }
+        }
+
+        for (rule, point) in fired {
+            self.fire(&rule, &point, log_file, utc, converter);
+        }
+    }
+
+    fn fire(
+        &mut self,
+        rule: &Rule,
+        point: &data::Point,
+        log_file: &str,
+        utc: bool,
+        converter: units::Converter,
+    ) {
+        let description = rule.describe(converter);
+        let message = format!(
+            "{:}: {:} at {:}, last at {:}",
+            rule.name,
+            description,
+            converter.format(f64::from(point.height)),
+            view::format_timestamp(point.time, utc)
+        );
+        log(log_file, &message);
+
+        if !rule.command.is_empty() {
+            run_command(&rule.command, rule, point);
+        }
+        if !rule.webhook.is_empty() {
+            post_webhook(rule.webhook.clone(), rule, &description, point);
+        }
+
+        self.history.push_back(Alert {
+            rule: rule.name.clone(),
+            message,
+        });
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.unseen += 1;
+    }
+
+    /// Shows a notification while there are alerts that have not been dismissed.
+    pub fn ui(&mut self, ctx: &egui::Context) {
+        if self.unseen == 0 {
+            return;
+        }
+
+        egui::Window::new("Alert")
+            .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 40.0))
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                for alert in self.history.iter().rev().take(self.unseen) {
+                    ui.colored_label(egui::Color32::from_rgb(255, 140, 60), &alert.rule);
+                    ui.label(&alert.message);
+                }
+                if ui.button("Dismiss").clicked() {
+                    self.unseen = 0;
+                }
+            });
+    }
+}
+
+/// The log file next to the saved settings, or in the working directory when there is nowhere to
+/// save settings.
+pub fn default_log_file() -> String {
+    directories_next::ProjectDirs::from("", "", "Plotter")
+        .map(|dirs| dirs.data_dir().join(LOG_FILE_NAME))
+        .and_then(|path| path.into_os_string().into_string().ok())
+        .unwrap_or_else(|| LOG_FILE_NAME.to_owned())
+}
+
+// Appends to the log file, unless it has been turned off by leaving it empty
+fn log(log_file: &str, message: &str) {
+    println!("Alert: {message}");
+    if log_file.is_empty() {
+        return;
+    }
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .and_then(|mut file| writeln!(file, "{message}"));
+    if let Err(e) = result {
+        eprintln!("Unable to write to {log_file}: {e}");
+    }
+}
+
+// The alert details are passed to the command as environment variables
+fn run_command(command: &str, rule: &Rule, point: &data::Point) {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    let result = shell
+        .arg(command)
+        .env("RAAP_RULE", &rule.name)
+        .env("RAAP_HEIGHT", point.height.to_string())
+        .env("RAAP_TIME", view::x_from_time(point.time).to_string())
+        .spawn();
+
+    match result {
+        // Wait for it elsewhere so it doesn't hold up drawing
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("Unable to run alert command: {e}"),
+    }
+}
+
+fn post_webhook(url: String, rule: &Rule, description: &str, point: &data::Point) {
+    let body = serde_json::json!({
+        "rule": rule.name,
+        "description": description,
+        "height": point.height,
+        "time": view::x_from_time(point.time),
+        "source": point.source.as_deref(),
+    })
+    .to_string();
+
+    thread::spawn(move || {
+        if let Err(e) = post(&url, &body) {
+            eprintln!("Unable to post alert to {url}: {e}");
+        }
+    });
+}
+
+// Only plain http is supported, and only to the local machine or network so alerts don't leave it
+fn post(url: &str, body: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or("Webhook URL must start with http://")?;
+    let (host, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') {
+        host.to_owned()
+    } else {
+        format!("{host}:80")
+    };
+
+    let address = address
+        .to_socket_addrs()?
+        .find(|address| is_local(address.ip()))
+        .ok_or("Webhook must be on this machine or the local network")?;
+    let mut stream = TcpStream::connect_timeout(&address, WEBHOOK_TIMEOUT)?;
+    stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+    stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    let code = status.split_whitespace().nth(1).unwrap_or_default();
+    if !code.starts_with('2') {
+        return Err(format!("Unexpected response {:?}", status.trim()).into());
+    }
+
+    Ok(())
+}
+
+// Loopback, private and link-local addresses
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_local(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                // Unique local fc00::/7 and link-local fe80::/10
+                ip.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::point;
+
+    fn rule() -> Rule {
+        Rule {
+            min_height: 50_000,
+            count: 2,
+            after_denoise: false,
+            ..Rule::default()
+        }
+    }
+
+    fn update(alerts: &mut Alerts, rules: &[Rule], store: &store::Store) {
+        let converter = units::Altitude::default().converter();
+        alerts.update(rules, store, None, "", true, converter);
+    }
+
+    #[test]
+    fn loaded_points_never_fire() {
+        let rules = [rule()];
+        let mut alerts = Alerts::default();
+
+        // Preloaded from the autosave
+        let mut store = store::Store::default();
+        store.extend([point(0.0, 60_000), point(1.0, 60_000)]);
+        update(&mut alerts, &rules, &store);
+        assert_eq!(alerts.history.len(), 0);
+
+        // Live points carry on, then a recording overlapping them is loaded
+        store.push(point(100.0, 30_000));
+        update(&mut alerts, &rules, &store);
+        store.extend([
+            point(50.0, 60_000),
+            point(51.0, 60_000),
+            point(52.0, 60_000),
+        ]);
+        update(&mut alerts, &rules, &store);
+        assert_eq!(alerts.history.len(), 0);
+
+        store.push(point(200.0, 60_000));
+        store.push(point(201.0, 60_000));
+        update(&mut alerts, &rules, &store);
+        assert_eq!(alerts.history.len(), 1);
+    }
+
+    #[test]
+    fn live_points_after_a_loaded_recording_fire() {
+        let rules = [rule()];
+        let mut alerts = Alerts::default();
+        let mut store = store::Store::default();
+        update(&mut alerts, &rules, &store);
+
+        // Loaded while disconnected, then connecting carries on from it
+        store.extend([point(0.0, 60_000), point(1.0, 60_000)]);
+        update(&mut alerts, &rules, &store);
+        assert_eq!(alerts.history.len(), 0);
+
+        store.push(point(10.0, 60_000));
+        store.push(point(11.0, 60_000));
+        update(&mut alerts, &rules, &store);
+        assert_eq!(alerts.history.len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{data, view};
+use eframe::egui;
+use eframe::egui::plot::{MarkerShape, PlotPoint, PlotPoints, PlotUi, Points, Text, VLine};
+use eframe::egui::{Align2, Color32};
+use std::time::SystemTime;
+
+const ANNOTATION_COLOR: Color32 = Color32::from_rgb(255, 220, 120);
+
+/// Bookmarks and notes left on the plot, kept in time order.
+#[derive(Default)]
+pub struct Annotations {
+    items: Vec<data::Annotation>,
+    pub open: bool,
+}
+
+impl Annotations {
+    pub fn as_slice(&self) -> &[data::Annotation] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Adds annotations from a recording, skipping any already here so loading the same one
+    /// twice doesn't double them up.
+    pub fn extend(&mut self, annotations: Vec<data::Annotation>) {
+        for annotation in annotations {
+            let duplicate = self
+                .items
+                .iter()
+                .any(|item| item.time == annotation.time && item.text == annotation.text);
+            if !duplicate {
+                self.items.push(annotation);
+            }
+        }
+        self.items.sort_by_key(|annotation| annotation.time);
+    }
+
+    /// Adds an annotation and opens the list so it can be named.
+    pub fn add(&mut self, time: SystemTime, height: Option<u32>, text: String) {
+        let index = self
+            .items
+            .partition_point(|annotation| annotation.time <= time);
+        self.items
+            .insert(index, data::Annotation { time, height, text });
+        self.open = true;
+    }
+
+    pub fn add_bookmark(&mut self, time: SystemTime) {
+        self.add(time, None, format!("Bookmark {:}", self.items.len() + 1));
+    }
+
+    pub fn draw(&self, plot_ui: &mut PlotUi, bounds: view::Bounds) {
+        for annotation in &self.items {
+            let x = view::x_from_time(annotation.time);
+            if x < bounds.min_x || x > bounds.max_x {
+                continue;
+            }
+
+            match annotation.height.map(f64::from) {
+                Some(y) if bounds.contains_y(y) => {
+                    plot_ui.points(
+                        Points::new(PlotPoints::new(vec![[x, y]]))
+                            .radius(4.0)
+                            .shape(MarkerShape::Square)
+                            .color(ANNOTATION_COLOR),
+                    );
+                    plot_ui.text(
+                        Text::new(PlotPoint::new(x, y), &annotation.text)
+                            .color(ANNOTATION_COLOR)
+                            .anchor(Align2::LEFT_BOTTOM),
+                    );
+                }
+                Some(_) => {}
+                None => {
+                    plot_ui.vline(VLine::new(x).color(ANNOTATION_COLOR).width(1.0));
+                    plot_ui.text(
+                        Text::new(PlotPoint::new(x, bounds.max_y), &annotation.text)
+                            .color(ANNOTATION_COLOR)
+                            .anchor(Align2::LEFT_TOP),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Lists the annotations for renaming and removing them. Returns the time of one to jump to.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        bookmark_time: SystemTime,
+        utc: bool,
+    ) -> Option<SystemTime> {
+        let mut jump = None;
+
+        if ui
+            .button("Add bookmark")
+            .on_hover_text("B, or alt + click on the plot to add a note")
+            .clicked()
+        {
+            self.add_bookmark(bookmark_time);
+        }
+        ui.separator();
+
+        let mut remove = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, annotation) in self.items.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .small_button(view::format_timestamp(annotation.time, utc))
+                        .on_hover_text("Jump to")
+                        .clicked()
+                    {
+                        jump = Some(annotation.time);
+                    }
+                    if ui.small_button("Delete").clicked() {
+                        remove = Some(index);
+                    }
+                });
+                ui.text_edit_singleline(&mut annotation.text);
+                ui.add_space(4.0);
+            }
+        });
+        if let Some(index) = remove {
+            self.items.remove(index);
+        }
+
+        jump
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{data, store};
+use std::fs;
+use std::mem;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Often enough that a crash or restart loses little, rarely enough that writing hours of points
+// doesn't matter
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Writes the live session to a file every so often, so it can carry on after a restart.
+pub struct Autosave {
+    last: Instant,
+    // The points as of the last write, kept so the next one only copies what has arrived since.
+    // Handed to the writer thread and back while a write is in progress.
+    snapshot: Vec<data::Point>,
+    // The store generation and pruned count the snapshot matches, `None` when it has to be
+    // copied again
+    generation: Option<u64>,
+    pruned: usize,
+    writer: Option<JoinHandle<Vec<data::Point>>>,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Autosave {
+            last: Instant::now(),
+            snapshot: vec![],
+            generation: None,
+            pruned: 0,
+            writer: None,
+        }
+    }
+}
+
+impl Autosave {
+    /// Starts writing the session once the interval has passed, unless the last write is still
+    /// in progress.
+    pub fn update(&mut self, path: &str, store: &store::Store, annotations: &[data::Annotation]) {
+        if path.is_empty() || self.last.elapsed() < INTERVAL {
+            return;
+        }
+        if self
+            .writer
+            .as_ref()
+            .map_or(false, |writer| !writer.is_finished())
+        {
+            return;
+        }
+
+        self.save(path, store, annotations);
+    }
+
+    /// Writes the session straight away, such as when disconnecting or closing. With `wait` this
+    /// only returns once the file has been written.
+    pub fn flush(
+        &mut self,
+        path: &str,
+        store: &store::Store,
+        annotations: &[data::Annotation],
+        wait: bool,
+    ) {
+        if path.is_empty() {
+            return;
+        }
+
+        self.save(path, store, annotations);
+        if wait {
+            self.join();
+        }
+    }
+
+    fn save(&mut self, path: &str, store: &store::Store, annotations: &[data::Annotation]) {
+        self.last = Instant::now();
+        self.join();
+
+        // Only points added anywhere other than the end change the generation, otherwise the
+        // store has just pruned from the front and pushed to the back since the last snapshot
+        let mut points = mem::take(&mut self.snapshot);
+        if self.generation == Some(store.generation()) {
+            let pruned = store.pruned() - self.pruned;
+            points.drain(..pruned.min(points.len()));
+            let copied = points.len();
+            points.extend_from_slice(&store.as_slice()[copied..]);
+        } else {
+            points.clear();
+            points.extend_from_slice(store.as_slice());
+        }
+        self.generation = Some(store.generation());
+        self.pruned = store.pruned();
+
+        if points.is_empty() && annotations.is_empty() {
+            self.snapshot = points;
+            return;
+        }
+
+        let plot = data::Plot {
+            points,
+            annotations: annotations.to_vec(),
+        };
+        let path = path.to_owned();
+        self.writer = Some(thread::spawn(move || {
+            if let Err(e) = write(&path, &plot) {
+                eprintln!("Failed to autosave to {:}: {e}", path);
+            }
+            plot.points
+        }));
+    }
+
+    // Waits for the write in progress and takes the snapshot back
+    fn join(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            match writer.join() {
+                Ok(points) => self.snapshot = points,
+                Err(_) => self.generation = None,
+            }
+        }
+    }
+}
+
+// Writes next to the file and then swaps it in, so a crash part way through leaves the last
+// autosave intact
+fn write(path: &str, plot: &data::Plot) -> Result<(), Box<dyn std::error::Error>> {
+    let temporary = format!("{:}.tmp", path);
+    data::write(&temporary, plot)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Reads the last autosave, if there is one.
+pub fn read(path: &str) -> Option<data::Plot> {
+    if path.is_empty() || fs::metadata(path).is_err() {
+        return None;
+    }
+
+    match data::read(path) {
+        Ok(plot) => Some(plot),
+        Err(e) => {
+            eprintln!("Unable to read autosave {:}: {e}", path);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::point;
+    use std::time::SystemTime;
+
+    fn times(points: &[data::Point]) -> Vec<SystemTime> {
+        points.iter().map(|point| point.time).collect()
+    }
+
+    // Each write only copies new points, the file should still match the whole store
+    #[test]
+    fn snapshots_follow_the_store() {
+        let path = std::env::temp_dir().join(format!("raap-{}-autosave.raap", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut autosave = Autosave::default();
+        let mut store = store::Store::default();
+        let check = |autosave: &mut Autosave, store: &store::Store| {
+            autosave.flush(path, store, &[], true);
+            assert_eq!(times(&read(path).unwrap().points), times(store.as_slice()));
+        };
+
+        for second in 0..10 {
+            store.push(point(f64::from(second), 30_000));
+        }
+        check(&mut autosave, &store);
+
+        for second in 10..20 {
+            store.push(point(f64::from(second), 30_000));
+        }
+        store.prune_before(point(5.0, 0).time);
+        check(&mut autosave, &store);
+
+        // Pruning past everything in the snapshot
+        for second in 20..40 {
+            store.push(point(f64::from(second), 30_000));
+        }
+        store.prune_before(point(25.0, 0).time);
+        check(&mut autosave, &store);
+
+        store.extend([point(26.5, 40_000), point(27.5, 40_000)]);
+        check(&mut autosave, &store);
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{adsb, aircraft, data, database, store};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime};
+
+// Mode C is reported in 100 ft steps and Mode S usually in 25 ft steps
+const TOLERANCE_FT: u32 = 150;
+// How close in time a Mode C reply has to be to a Mode S altitude to back it up
+const MATCH_WINDOW: Duration = Duration::from_secs(2);
+// A trace has to follow the aircraft for this many of its altitudes, and most of them
+const MIN_MATCHES: usize = 5;
+const MIN_FRACTION: f64 = 0.6;
+// Checking more altitudes than this per aircraft doesn't make a match any more certain
+const MAX_SAMPLES: usize = 200;
+const REFRESH: Duration = Duration::from_millis(250);
+
+struct Aircraft {
+    callsign: Option<String>,
+    altitudes: VecDeque<(SystemTime, u32)>,
+    // Aircraft only sending callsigns have no altitudes to tell when they were last heard
+    last_seen: SystemTime,
+}
+
+/// An identified aircraft whose Mode S altitudes are followed by a Mode C trace.
+#[derive(Clone)]
+pub struct Match {
+    pub icao: u32,
+    pub callsign: Option<String>,
+    // Where the trace last agreed with the aircraft, for labelling it
+    pub time: SystemTime,
+    pub height: u32,
+    pub matched: usize,
+    pub samples: usize,
+}
+
+impl Match {
+    pub fn label(&self, entry: Option<&database::Entry>) -> String {
+        aircraft::label(self.icao, self.callsign.as_deref(), entry)
+    }
+}
+
+/// Matches anonymous Mode C altitude traces against altitudes reported by identified Mode S
+/// aircraft at the same time.
+#[derive(Default)]
+pub struct Correlator {
+    aircraft: HashMap<u32, Aircraft>,
+    matches: Vec<Match>,
+    computed_at: Option<Instant>,
+}
+
+impl Correlator {
+    pub fn push(&mut self, report: adsb::Report) {
+        let aircraft = self
+            .aircraft
+            .entry(report.icao)
+            .or_insert_with(|| Aircraft {
+                callsign: None,
+                altitudes: VecDeque::new(),
+                last_seen: report.time,
+            });
+        aircraft.last_seen = aircraft.last_seen.max(report.time);
+
+        match report.kind {
+            adsb::ReportKind::Altitude(altitude) => {
+                aircraft.altitudes.push_back((report.time, altitude));
+            }
+            adsb::ReportKind::Callsign(callsign) => {
+                if !callsign.is_empty() {
+                    aircraft.callsign = Some(callsign);
+                }
+            }
+            adsb::ReportKind::GnssHeight(_)
+            | adsb::ReportKind::GnssDifference(_)
+            | adsb::ReportKind::Seen => {}
+        }
+    }
+
+    pub fn prune(&mut self, cutoff: SystemTime) {
+        for aircraft in self.aircraft.values_mut() {
+            let count = aircraft
+                .altitudes
+                .partition_point(|(time, _)| *time < cutoff);
+            aircraft.altitudes.drain(..count);
+        }
+        self.aircraft
+            .retain(|_, aircraft| !aircraft.altitudes.is_empty() || aircraft.last_seen >= cutoff);
+    }
+
+    pub fn clear(&mut self) {
+        self.aircraft.clear();
+        self.matches.clear();
+    }
+
+    /// Works out which aircraft have a matching Mode C trace between `start` and `end`.
+    pub fn update(
+        &mut self,
+        points: &[data::Point],
+        keep: Option<&[bool]>,
+        start: SystemTime,
+        end: SystemTime,
+    ) {
+        let fresh = self
+            .computed_at
+            .map_or(false, |computed_at| computed_at.elapsed() < REFRESH);
+        if fresh {
+            return;
+        }
+        self.computed_at = Some(Instant::now());
+
+        self.matches = self
+            .aircraft
+            .iter()
+            .filter_map(|(icao, aircraft)| {
+                let from = aircraft
+                    .altitudes
+                    .partition_point(|(time, _)| *time < start);
+                let to = aircraft.altitudes.partition_point(|(time, _)| *time <= end);
+                if to <= from {
+                    return None;
+                }
+
+                let stride = (to - from + MAX_SAMPLES - 1) / MAX_SAMPLES;
+                let mut samples = 0;
+                let mut matched = 0;
+                let mut last = None;
+                for (time, height) in aircraft.altitudes.range(from..to).step_by(stride) {
+                    samples += 1;
+                    if has_reply(points, keep, *time, *height) {
+                        matched += 1;
+                        last = Some((*time, *height));
+                    }
+                }
+
+                let (time, height) = last?;
+                let fraction = matched as f64 / samples as f64;
+                (matched >= MIN_MATCHES && fraction >= MIN_FRACTION).then(|| Match {
+                    icao: *icao,
+                    callsign: aircraft.callsign.clone(),
+                    time,
+                    height,
+                    matched,
+                    samples,
+                })
+            })
+            .collect();
+    }
+
+    pub fn callsign(&self, icao: u32) -> Option<&str> {
+        self.aircraft.get(&icao)?.callsign.as_deref()
+    }
+
+    pub fn matches(&self) -> &[Match] {
+        &self.matches
+    }
+
+    /// The matched aircraft whose altitude was closest to the Mode C point when it was received.
+    pub fn find(&self, point: &data::Point) -> Option<&Match> {
+        if point.message.icao().is_some() {
+            return None;
+        }
+
+        self.matches
+            .iter()
+            .filter_map(|m| {
+                let aircraft = self.aircraft.get(&m.icao)?;
+                let from = aircraft
+                    .altitudes
+                    .partition_point(|(time, _)| *time + MATCH_WINDOW < point.time);
+                aircraft
+                    .altitudes
+                    .range(from..)
+                    .take_while(|(time, _)| *time <= point.time + MATCH_WINDOW)
+                    .map(|(_, height)| height.abs_diff(point.height))
+                    .filter(|difference| *difference <= TOLERANCE_FT)
+                    .min()
+                    .map(|difference| (m, difference))
+            })
+            .min_by_key(|(_, difference)| *difference)
+            .map(|(m, _)| m)
+    }
+}
+
+// Whether a kept Mode C reply was received close to the given time and height
+fn has_reply(points: &[data::Point], keep: Option<&[bool]>, time: SystemTime, height: u32) -> bool {
+    let range = store::range(points, time - MATCH_WINDOW, time + MATCH_WINDOW);
+    range.into_iter().any(|index| {
+        points[index].message.icao().is_none()
+            && points[index].height.abs_diff(height) <= TOLERANCE_FT
+            && keep.map_or(true, |keep| keep.get(index).copied().unwrap_or(false))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn report(icao: u32, secs: u64, kind: adsb::ReportKind) -> adsb::Report {
+        adsb::Report {
+            icao,
+            verified: true,
+            time: UNIX_EPOCH + Duration::from_secs(secs),
+            kind,
+        }
+    }
+
+    #[test]
+    fn prune_forgets_aircraft_no_longer_heard() {
+        let mut correlator = Correlator::default();
+        correlator.push(report(1, 10, adsb::ReportKind::Altitude(30_000)));
+        correlator.push(report(
+            1,
+            11,
+            adsb::ReportKind::Callsign("KLM1023".to_owned()),
+        ));
+        correlator.push(report(2, 15, adsb::ReportKind::Callsign("BAW1".to_owned())));
+        correlator.push(report(3, 30, adsb::ReportKind::Altitude(20_000)));
+
+        correlator.prune(UNIX_EPOCH + Duration::from_secs(12));
+        assert_eq!(correlator.callsign(1), None);
+        assert_eq!(correlator.callsign(2), Some("BAW1"));
+        assert_eq!(correlator.aircraft.len(), 2);
+
+        correlator.prune(UNIX_EPOCH + Duration::from_secs(20));
+        assert_eq!(correlator.aircraft.len(), 1);
+    }
+}
@@ -3,22 +3,89 @@ use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-const HEADER: &[u8] = &[0xd, 0x1, 0xa, 0x0];
+// Files saved before points carried their message and source, and before annotations
+const HEADER_V1: &[u8] = &[0xd, 0x1, 0xa, 0x0];
+const HEADER: &[u8] = &[0xd, 0x1, 0xa, 0x1];
+// Source index for points without a known source
+const NO_SOURCE: u32 = u32::MAX;
+// Height of annotations that mark a moment rather than a point on the plot
+const NO_HEIGHT: u32 = u32::MAX;
 
 #[derive(Clone)]
 pub struct Point {
     pub height: u32,
     pub time: SystemTime,
+    pub message: Message,
+    // The receiver the point came from
+    pub source: Option<Arc<str>>,
+}
+
+/// The reply a point was decoded from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Message {
+    // Loaded from a file that did not record it
+    Unknown,
+    // The raw Mode A/C code, as sent by the receiver
+    ModeAc { code: u16 },
+    // An altitude reported by a Mode S transponder, with its ICAO address
+    ModeS { icao: u32 },
+}
+
+impl Message {
+    /// The address of the aircraft that sent it, only known for Mode S.
+    pub fn icao(self) -> Option<u32> {
+        match self {
+            Message::ModeS { icao } => Some(icao),
+            _ => None,
+        }
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            Message::Unknown => 0,
+            Message::ModeAc { .. } => 1,
+            Message::ModeS { .. } => 2,
+        }
+    }
+
+    fn code(self) -> u32 {
+        match self {
+            Message::Unknown => 0,
+            Message::ModeAc { code } => u32::from(code),
+            Message::ModeS { icao } => icao,
+        }
+    }
+
+    fn from_parts(tag: u8, code: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        match tag {
+            0 => Ok(Message::Unknown),
+            1 => Ok(Message::ModeAc {
+                code: u16::try_from(code)?,
+            }),
+            2 => Ok(Message::ModeS { icao: code }),
+            _ => Err(format!("Unknown message type {tag}").into()),
+        }
+    }
+}
+
+/// A note left on the plot, at an altitude or, for bookmarks, across the whole moment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    pub time: SystemTime,
+    pub height: Option<u32>,
+    pub text: String,
 }
 
 #[derive(Clone)]
 pub struct Plot {
     pub points: Vec<Point>,
+    pub annotations: Vec<Annotation>,
 }
 
-pub fn write(path: &str, plot: Plot) -> Result<(), Box<dyn std::error::Error>> {
+pub fn write(path: &str, plot: &Plot) -> Result<(), Box<dyn std::error::Error>> {
     let file = File::create(path)?;
     let file_writer = BufWriter::new(file);
     let mut writer = ZlibEncoder::new(file_writer, Compression::fast());
@@ -26,14 +93,47 @@ pub fn write(path: &str, plot: Plot) -> Result<(), Box<dyn std::error::Error>> {
     // Header
     writer.write_all(HEADER)?;
 
+    // Receivers the points came from, each point refers to one by index
+    let mut sources: Vec<Arc<str>> = vec![];
+    for source in plot.points.iter().filter_map(|point| point.source.as_ref()) {
+        if !sources.contains(source) {
+            sources.push(source.clone());
+        }
+    }
+    writer.write_all(&u32::try_from(sources.len())?.to_be_bytes())?;
+    for source in &sources {
+        writer.write_all(&u32::try_from(source.len())?.to_be_bytes())?;
+        writer.write_all(source.as_bytes())?;
+    }
+
     // Number of points
     let size = u32::try_from(plot.points.len())?;
     writer.write_all(&size.to_be_bytes())?;
 
-    for point in plot.points {
+    for point in &plot.points {
         writer.write_all(&point.height.to_be_bytes())?;
         let time = point.time.duration_since(UNIX_EPOCH)?.as_millis();
         writer.write_all(&time.to_be_bytes())?;
+        writer.write_all(&[point.message.tag()])?;
+        writer.write_all(&point.message.code().to_be_bytes())?;
+
+        let source = match &point.source {
+            Some(source) => {
+                let index = sources.iter().position(|s| s == source);
+                u32::try_from(index.ok_or("Missing source")?)?
+            }
+            None => NO_SOURCE,
+        };
+        writer.write_all(&source.to_be_bytes())?;
+    }
+
+    writer.write_all(&u32::try_from(plot.annotations.len())?.to_be_bytes())?;
+    for annotation in &plot.annotations {
+        let time = annotation.time.duration_since(UNIX_EPOCH)?.as_millis();
+        writer.write_all(&time.to_be_bytes())?;
+        writer.write_all(&annotation.height.unwrap_or(NO_HEIGHT).to_be_bytes())?;
+        writer.write_all(&u32::try_from(annotation.text.len())?.to_be_bytes())?;
+        writer.write_all(annotation.text.as_bytes())?;
     }
 
     writer.flush()?;
@@ -59,13 +159,23 @@ pub fn read(path: &str) -> Result<Plot, Box<dyn std::error::Error>> {
     let mut byte_header = [0; 4];
     reader.read_exact(&mut byte_header)?;
 
-    if byte_header != HEADER {
+    let v1 = if byte_header == HEADER {
+        false
+    } else if byte_header == HEADER_V1 {
+        true
+    } else {
         return Err("Unexpected file header".into());
+    };
+
+    let mut sources: Vec<Arc<str>> = vec![];
+    if !v1 {
+        let count = read_u32(&mut reader)?;
+        for _ in 0..count {
+            sources.push(read_string(&mut reader)?.into());
+        }
     }
 
-    let mut size_buf = [0; 4];
-    reader.read_exact(&mut size_buf)?;
-    let size = u32::from_be_bytes(size_buf);
+    let size = read_u32(&mut reader)?;
 
     let mut points: Vec<Point> = vec![];
     for _ in 0..size {
@@ -73,16 +183,218 @@ pub fn read(path: &str) -> Result<Plot, Box<dyn std::error::Error>> {
         reader.read_exact(&mut height_buf)?;
         let height = u32::from_be_bytes(height_buf);
 
-        let mut time_buf = [0; 16];
-        reader.read_exact(&mut time_buf)?;
-        let epoch = u128::from_be_bytes(time_buf);
-        let epoch64 = u64::try_from(epoch)?;
-        let time = UNIX_EPOCH
-            .checked_add(Duration::from_millis(epoch64))
-            .ok_or("Failed to parse time")?;
+        let time = read_time(&mut reader)?;
 
-        points.push(Point { height, time });
+        let (message, source) = if v1 {
+            (Message::Unknown, None)
+        } else {
+            let mut tag_buf = [0; 1];
+            reader.read_exact(&mut tag_buf)?;
+            let message = Message::from_parts(tag_buf[0], read_u32(&mut reader)?)?;
+
+            let source = match read_u32(&mut reader)? {
+                NO_SOURCE => None,
+                index => Some(
+                    sources
+                        .get(usize::try_from(index)?)
+                        .ok_or("Unknown source")?
+                        .clone(),
+                ),
+            };
+
+            (message, source)
+        };
+
+        points.push(Point {
+            height,
+            time,
+            message,
+            source,
+        });
+    }
+
+    let mut annotations: Vec<Annotation> = vec![];
+    if !v1 {
+        let count = read_u32(&mut reader)?;
+        for _ in 0..count {
+            let time = read_time(&mut reader)?;
+            let height = match read_u32(&mut reader)? {
+                NO_HEIGHT => None,
+                height => Some(height),
+            };
+            let text = read_string(&mut reader)?;
+
+            annotations.push(Annotation { time, height, text });
+        }
     }
 
-    Ok(Plot { points })
+    Ok(Plot {
+        points,
+        annotations,
+    })
+}
+
+// Milliseconds since the unix epoch
+fn read_time(reader: &mut dyn Read) -> Result<SystemTime, Box<dyn std::error::Error>> {
+    let mut time_buf = [0; 16];
+    reader.read_exact(&mut time_buf)?;
+    let epoch = u128::from_be_bytes(time_buf);
+    let epoch64 = u64::try_from(epoch)?;
+    UNIX_EPOCH
+        .checked_add(Duration::from_millis(epoch64))
+        .ok_or_else(|| "Failed to parse time".into())
+}
+
+fn read_u32(reader: &mut dyn Read) -> Result<u32, Box<dyn std::error::Error>> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+// A length followed by UTF-8. Only what is actually in the file is read, so a corrupt length
+// can't ask for a huge buffer up front.
+fn read_string(reader: &mut dyn Read) -> Result<String, Box<dyn std::error::Error>> {
+    let len = u64::from(read_u32(reader)?);
+    let mut buf = vec![];
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err("Unexpected end of file".into());
+    }
+
+    Ok(String::from_utf8(buf)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A file in the temporary directory that is removed afterwards
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            TempFile(std::env::temp_dir().join(format!("raap-{}-{name}", std::process::id())))
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+
+        fn with_contents(name: &str, contents: &[u8]) -> Self {
+            let file = TempFile::new(name);
+            std::fs::write(&file.0, contents).unwrap();
+            file
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn time(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    // The fields every version starts a point with
+    fn point_bytes(height: u32, millis: u64) -> Vec<u8> {
+        [
+            height.to_be_bytes().to_vec(),
+            u128::from(millis).to_be_bytes().to_vec(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn round_trip() {
+        let receiver: Arc<str> = "receiver:30002".into();
+        let plot = Plot {
+            points: vec![
+                Point {
+                    height: 35_000,
+                    time: time(1_700_000_000_123),
+                    message: Message::ModeAc { code: 0x1234 },
+                    source: Some(receiver.clone()),
+                },
+                Point {
+                    height: 65_025,
+                    time: time(1_700_000_000_456),
+                    message: Message::ModeS { icao: 0xADF7C8 },
+                    source: None,
+                },
+                Point {
+                    height: 12_000,
+                    time: time(1_700_000_001_000),
+                    message: Message::Unknown,
+                    source: Some(receiver),
+                },
+            ],
+            annotations: vec![
+                Annotation {
+                    time: time(1_700_000_000_000),
+                    height: None,
+                    text: "Receiver restarted".to_owned(),
+                },
+                Annotation {
+                    time: time(1_700_000_000_500),
+                    height: Some(65_000),
+                    text: "U-2 départ".to_owned(),
+                },
+            ],
+        };
+
+        let file = TempFile::new("round-trip.raap");
+        write(file.path(), &plot).unwrap();
+        let read = read(file.path()).unwrap();
+
+        assert_eq!(read.annotations, plot.annotations);
+        assert_eq!(read.points.len(), plot.points.len());
+        for (read, written) in read.points.iter().zip(&plot.points) {
+            assert_eq!(read.height, written.height);
+            assert_eq!(read.time, written.time);
+            assert_eq!(read.message, written.message);
+            assert_eq!(read.source, written.source);
+        }
+    }
+
+    #[test]
+    fn reads_v1() {
+        let contents = [
+            HEADER_V1.to_vec(),
+            2u32.to_be_bytes().to_vec(),
+            point_bytes(30_000, 1000),
+            point_bytes(31_000, 2000),
+        ]
+        .concat();
+        let file = TempFile::with_contents("v1.raap", &contents);
+
+        let plot = read(file.path()).unwrap();
+        assert_eq!(plot.points.len(), 2);
+        assert_eq!(plot.points[1].height, 31_000);
+        assert_eq!(plot.points[1].time, time(2000));
+        assert_eq!(plot.points[1].message, Message::Unknown);
+        assert!(plot.annotations.is_empty());
+    }
+
+    #[test]
+    fn rejects_huge_lengths() {
+        let contents = [
+            HEADER.to_vec(),
+            1u32.to_be_bytes().to_vec(),
+            u32::MAX.to_be_bytes().to_vec(),
+            b"short".to_vec(),
+        ]
+        .concat();
+        let file = TempFile::with_contents("huge.raap", &contents);
+
+        assert!(read(file.path()).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_headers() {
+        let file = TempFile::with_contents("unknown.raap", &[0xd, 0x1, 0xa, 0x7]);
+        assert!(read(file.path()).is_err());
+    }
 }
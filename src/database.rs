@@ -0,0 +1,341 @@
+use eframe::egui;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::sync::mpsc;
+use std::thread;
+
+// Column names used for each field by the formats that have a header, compared in lower case.
+// basestation.sqb exports use the names from its Aircraft table.
+const ICAO_COLUMNS: [&str; 4] = ["icao", "icao24", "hex", "modes"];
+const REGISTRATION_COLUMNS: [&str; 3] = ["r", "reg", "registration"];
+const TYPE_COLUMNS: [&str; 5] = ["t", "type", "typecode", "icaotype", "icaotypecode"];
+const OPERATOR_COLUMNS: [&str; 5] = ["ownop", "operator", "owner", "registeredowners", "o"];
+// tar1090's aircraft.csv has no header: icao;registration;type;flags;description;year;owner
+const TAR1090_COLUMNS: Columns = Columns {
+    icao: 0,
+    registration: Some(1),
+    type_code: Some(2),
+    operator: Some(6),
+};
+
+/// What is known about an aircraft from the local database. Fields the database didn't have
+/// are empty.
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    pub registration: String,
+    pub type_code: String,
+    pub operator: String,
+}
+
+impl Entry {
+    /// Registration and type, for labelling traces.
+    pub fn short(&self) -> String {
+        [self.registration.as_str(), self.type_code.as_str()]
+            .iter()
+            .filter(|field| !field.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Aircraft details looked up by ICAO address, read from a local file. Nothing is ever fetched
+/// over the network.
+#[derive(Default)]
+pub struct Database {
+    entries: HashMap<u32, Entry>,
+    // The file the entries were read from, so a changed setting loads it again
+    path: String,
+    // Large exports take a while to parse, so they are read on another thread
+    loading: Option<mpsc::Receiver<Result<HashMap<u32, Entry>, String>>>,
+}
+
+impl Database {
+    /// Starts reading the database at `path` if it isn't the one already loaded, and picks up
+    /// the entries once they have been read. An empty path unloads it.
+    pub fn sync(&mut self, path: &str, ctx: &egui::Context) {
+        if self.path != path {
+            self.path = path.to_owned();
+            self.entries.clear();
+            // Anything still being read from the old path is dropped when it arrives
+            self.loading = None;
+
+            if !path.is_empty() {
+                let (tx, rx) = mpsc::channel();
+                let path = path.to_owned();
+                let ctx = ctx.clone();
+                thread::spawn(move || {
+                    // The receiver is gone if the path changed again, which is fine
+                    let _ = tx.send(read(&path).map_err(|e| e.to_string()));
+                    ctx.request_repaint();
+                });
+                self.loading = Some(rx);
+            }
+        }
+
+        let result = match self.loading.as_ref().map(mpsc::Receiver::try_recv) {
+            Some(Ok(result)) => result,
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+            Some(Err(mpsc::TryRecvError::Disconnected)) => Err("Reading stopped".to_owned()),
+        };
+        self.loading = None;
+        match result {
+            Ok(entries) => {
+                println!("Loaded {:} aircraft from {:}", entries.len(), self.path);
+                self.entries = entries;
+            }
+            Err(e) => eprintln!("Unable to read aircraft database {:}: {e}", self.path),
+        }
+    }
+
+    pub fn get(&self, icao: u32) -> Option<&Entry> {
+        self.entries.get(&icao)
+    }
+}
+
+// Which column each field is in
+struct Columns {
+    icao: usize,
+    registration: Option<usize>,
+    type_code: Option<usize>,
+    operator: Option<usize>,
+}
+
+/// Reads a CSV or JSON database, optionally gzipped, picking the format from the file name.
+pub fn read(path: &str) -> Result<HashMap<u32, Entry>, Box<dyn std::error::Error>> {
+    let file = BufReader::new(File::open(path)?);
+    let lower = path.to_lowercase();
+    let (name, mut reader): (&str, Box<dyn Read>) = match lower.strip_suffix(".gz") {
+        Some(name) => (name, Box::new(GzDecoder::new(file))),
+        None => (&lower, Box::new(file)),
+    };
+
+    // Exports aren't always UTF-8, and a few mangled names are better than none at all
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let contents = String::from_utf8_lossy(&bytes);
+
+    let entries = if name.ends_with(".json") {
+        read_json(&contents)?
+    } else {
+        read_csv(&contents)?
+    };
+    if entries.is_empty() {
+        return Err("No aircraft found".into());
+    }
+
+    Ok(entries)
+}
+
+// Either an object keyed by address as used by tar1090, or a list of objects with an address
+// field, as one array or one object per line
+fn read_json(contents: &str) -> Result<HashMap<u32, Entry>, Box<dyn std::error::Error>> {
+    let values = match serde_json::from_str::<Value>(contents) {
+        Ok(Value::Object(object)) => {
+            return Ok(object
+                .iter()
+                .filter_map(|(key, value)| Some((parse_icao(key)?, json_entry(value))))
+                .collect());
+        }
+        Ok(Value::Array(values)) => values,
+        Ok(_) => return Err("Expected an object or a list of aircraft".into()),
+        Err(_) => contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()?,
+    };
+
+    Ok(values
+        .iter()
+        .filter_map(|value| {
+            let icao = json_field(value, &ICAO_COLUMNS).and_then(|icao| parse_icao(&icao))?;
+            Some((icao, json_entry(value)))
+        })
+        .collect())
+}
+
+fn json_entry(value: &Value) -> Entry {
+    Entry {
+        registration: json_field(value, &REGISTRATION_COLUMNS).unwrap_or_default(),
+        type_code: json_field(value, &TYPE_COLUMNS).unwrap_or_default(),
+        operator: json_field(value, &OPERATOR_COLUMNS).unwrap_or_default(),
+    }
+}
+
+fn json_field(value: &Value, names: &[&str]) -> Option<String> {
+    value
+        .as_object()?
+        .iter()
+        .find(|(key, _)| names.contains(&key.to_lowercase().as_str()))
+        .and_then(|(_, value)| value.as_str())
+        .map(|field| field.trim().to_owned())
+        .filter(|field| !field.is_empty())
+}
+
+// Semicolon or comma separated, with a header naming the columns unless it is tar1090's
+fn read_csv(contents: &str) -> Result<HashMap<u32, Entry>, Box<dyn std::error::Error>> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let first = match lines.next() {
+        Some(first) => first,
+        None => return Ok(HashMap::new()),
+    };
+    let separator = if first.contains(';') { ';' } else { ',' };
+
+    let header = split_csv(first, separator);
+    let (columns, first) = if header.first().and_then(|icao| parse_icao(icao)).is_some() {
+        (TAR1090_COLUMNS, Some(first))
+    } else {
+        let find = |names: &[&str]| {
+            header
+                .iter()
+                .position(|column| names.contains(&column.to_lowercase().as_str()))
+        };
+        let columns = Columns {
+            icao: find(&ICAO_COLUMNS).ok_or("No ICAO address column")?,
+            registration: find(&REGISTRATION_COLUMNS),
+            type_code: find(&TYPE_COLUMNS),
+            operator: find(&OPERATOR_COLUMNS),
+        };
+        (columns, None)
+    };
+
+    Ok(first
+        .into_iter()
+        .chain(lines)
+        .filter_map(|line| {
+            let fields = split_csv(line, separator);
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|column| fields.get(column))
+                    .map_or_else(String::new, |field| field.trim().to_owned())
+            };
+
+            let icao = parse_icao(fields.get(columns.icao)?)?;
+            Some((
+                icao,
+                Entry {
+                    registration: field(columns.registration),
+                    type_code: field(columns.type_code),
+                    operator: field(columns.operator),
+                },
+            ))
+        })
+        .collect())
+}
+
+// Fields may be quoted to hold the separator, with doubled quotes inside them
+fn split_csv(line: &str, separator: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+fn parse_icao(text: &str) -> Option<u32> {
+    let text = text.trim();
+    // from_str_radix would take a leading sign
+    if text.len() != 6 || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(text, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(entries: &HashMap<u32, Entry>, icao: u32) -> (&str, &str, &str) {
+        let entry = &entries[&icao];
+        (&entry.registration, &entry.type_code, &entry.operator)
+    }
+
+    #[test]
+    fn parses_icao_addresses() {
+        assert_eq!(parse_icao("4840D6"), Some(0x4840D6));
+        assert_eq!(parse_icao(" a05f21 "), Some(0xA05F21));
+        assert_eq!(parse_icao("4840D"), None);
+        assert_eq!(parse_icao("4840D6F"), None);
+        assert_eq!(parse_icao("4840G6"), None);
+        assert_eq!(parse_icao("+840D6"), None);
+        assert_eq!(parse_icao("~4840d"), None);
+        assert_eq!(parse_icao(""), None);
+    }
+
+    #[test]
+    fn splits_quoted_fields() {
+        assert_eq!(
+            split_csv("4840D6,\"KLM, Royal Dutch\",\"say \"\"hi\"\"\",\r\n", ','),
+            ["4840D6", "KLM, Royal Dutch", "say \"hi\"", ""]
+        );
+        assert_eq!(split_csv("a;b,c;", ';'), ["a", "b,c", ""]);
+    }
+
+    #[test]
+    fn reads_headerless_tar1090_csv() {
+        let contents = "4840D6;PH-BHA;B789;00;BOEING 787-9;2015;KLM\n\
+                        A05F21;N1234;C172;00;;;\n\
+                        ZZZZZZ;bad;;;;;\n";
+        let entries = read_csv(contents).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entry(&entries, 0x4840D6), ("PH-BHA", "B789", "KLM"));
+        assert_eq!(entry(&entries, 0xA05F21), ("N1234", "C172", ""));
+    }
+
+    #[test]
+    fn reads_csv_with_a_header() {
+        let contents = "ModeS,Registration,ICAOTypeCode,RegisteredOwners\r\n\
+                        4840D6,PH-BHA,B789,\"KLM, Royal Dutch Airlines\"\r\n\
+                        4840D,PH-BAD,B789,Truncated\r\n";
+        let entries = read_csv(contents).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entry(&entries, 0x4840D6),
+            ("PH-BHA", "B789", "KLM, Royal Dutch Airlines")
+        );
+        assert!(read_csv("registration,type\nPH-BHA,B789\n").is_err());
+    }
+
+    #[test]
+    fn reads_json_objects_arrays_and_lines() {
+        let object = r#"{"4840d6": {"r": "PH-BHA", "t": "B789"}, "nothex": {"r": "X"}}"#;
+        let entries = read_json(object).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entry(&entries, 0x4840D6), ("PH-BHA", "B789", ""));
+
+        let array = r#"[{"icao24": "4840d6", "registration": "PH-BHA", "operator": "KLM"},
+                        {"icao24": "12", "registration": "short"}]"#;
+        let entries = read_json(array).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entry(&entries, 0x4840D6), ("PH-BHA", "", "KLM"));
+
+        let lines = "{\"hex\": \"4840d6\", \"r\": \"PH-BHA\"}\n\n\
+                     {\"hex\": \"a05f21\", \"t\": \"C172\"}\n";
+        let entries = read_json(lines).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entry(&entries, 0xA05F21), ("", "C172", ""));
+
+        assert!(read_json("\"just a string\"").is_err());
+        assert!(read_json("{\"hex\": \"4840d6\"}\nnot json\n").is_err());
+    }
+}
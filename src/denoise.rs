@@ -1,48 +1,570 @@
 use crate::data;
-use std::time::{SystemTime, UNIX_EPOCH};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
 
-pub fn denoise(points: &[data::Point]) -> Vec<data::Point> {
-    let mut out: Vec<data::Point> = vec![];
+mod dbscan;
+mod median;
+mod neighbours;
+mod ransac;
 
-    for (index, point) in points.iter().enumerate() {
-        if should_apply_point(index, point, points) {
-            out.push(point.clone());
+/// A way of deciding which points are real and which are noise.
+pub trait Algorithm {
+    /// How far either side of a point the data is needed to classify it.
+    fn context(&self) -> Duration;
+
+    /// Returns whether each point in `range` should be kept. The points must be ordered by time,
+    /// and those outside of `range` are only used as context. Mode S points are always kept and
+    /// aren't used as context, as their noise is nothing like Mode A/C's.
+    fn classify(&self, points: &[data::Point], range: Range<usize>) -> Vec<bool>;
+
+    fn ui(&mut self, ui: &mut egui::Ui);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Kind {
+    #[default]
+    Neighbours,
+    RollingMedian,
+    Dbscan,
+    Ransac,
+}
+
+impl Kind {
+    const ALL: [Kind; 4] = [
+        Kind::Neighbours,
+        Kind::RollingMedian,
+        Kind::Dbscan,
+        Kind::Ransac,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Neighbours => "Neighbour count",
+            Kind::RollingMedian => "Rolling median",
+            Kind::Dbscan => "DBSCAN",
+            Kind::Ransac => "RANSAC",
         }
     }
+}
 
-    out
+/// The selected algorithm, along with the parameters of every algorithm so switching between
+/// them does not lose any tuning.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct Params {
+    pub kind: Kind,
+    pub neighbours: neighbours::Neighbours,
+    pub rolling_median: median::RollingMedian,
+    pub dbscan: dbscan::Dbscan,
+    pub ransac: ransac::Ransac,
 }
 
-fn should_apply_point(index: usize, point: &data::Point, points: &[data::Point]) -> bool {
-    let mut matching = 0;
-    for point_2 in points[index..].iter().chain(points[..index].iter()) {
-        if point.time == point_2.time && point.height == point_2.height {
-            continue;
+impl Params {
+    pub fn algorithm(&self) -> &dyn Algorithm {
+        match self.kind {
+            Kind::Neighbours => &self.neighbours,
+            Kind::RollingMedian => &self.rolling_median,
+            Kind::Dbscan => &self.dbscan,
+            Kind::Ransac => &self.ransac,
         }
+    }
+
+    fn algorithm_mut(&mut self) -> &mut dyn Algorithm {
+        match self.kind {
+            Kind::Neighbours => &mut self.neighbours,
+            Kind::RollingMedian => &mut self.rolling_median,
+            Kind::Dbscan => &mut self.dbscan,
+            Kind::Ransac => &mut self.ransac,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Algorithm")
+            .selected_text(self.kind.name())
+            .show_ui(ui, |ui| {
+                for kind in Kind::ALL {
+                    ui.selectable_value(&mut self.kind, kind, kind.name());
+                }
+            });
+
+        self.algorithm_mut().ui(ui);
+    }
+}
 
-        if time_diff(&point.time, &point_2.time) > 10 {
-            // Nothing within 10 seconds
-            return false;
+/// Keeps the raw points untouched and tracks which of them pass the filter, so denoising can be
+/// previewed, applied and undone without losing any data.
+#[derive(Default)]
+pub struct Filter {
+    pub preview: bool,
+    pub show_rejected: bool,
+    applied: Option<Params>,
+    cache: Option<Cache>,
+    // Changes whenever the classification starts again from scratch
+    generation: u64,
+}
+
+struct Cache {
+    params: Params,
+    // One entry for each classified point, starting at the oldest point
+    keep: VecDeque<bool>,
+    rejected: usize,
+    last: Option<SystemTime>,
+}
+
+impl Filter {
+    pub fn apply(&mut self, params: Params) {
+        self.applied = Some(params);
+        self.preview = false;
+    }
+
+    pub fn undo(&mut self) {
+        self.applied = None;
+    }
+
+    /// Classifies every point again, for when points were added before ones already classified.
+    pub fn restart(&mut self) {
+        if self.cache.take().is_some() {
+            self.generation += 1;
         }
+    }
+
+    pub fn is_applied(&self) -> bool {
+        self.applied.is_some()
+    }
+
+    /// Classifies any new points. Previewing takes the parameters currently being edited over the
+    /// applied ones.
+    ///
+    /// With live data a point is only classified once a full window of newer data has arrived
+    /// after it, so the points after the end of `keep` are still pending. Each point is only
+    /// classified once, as new points arrive.
+    pub fn update(&mut self, points: &[data::Point], params: &Params, live: Option<SystemTime>) {
+        let params = match (self.preview, self.applied) {
+            (true, _) => *params,
+            (false, Some(applied)) => applied,
+            (false, None) => {
+                if self.cache.take().is_some() {
+                    self.generation += 1;
+                }
+                return;
+            }
+        };
 
-        let height_delta = (i64::from(point.height) - i64::from(point_2.height)).abs();
+        // Start again if the parameters changed or the points were replaced
+        let stale = self.cache.as_ref().map_or(true, |cache| {
+            cache.params != params
+                || cache.keep.len() > points.len()
+                || cache.last != cache.keep.len().checked_sub(1).map(|i| points[i].time)
+        });
+        if stale {
+            self.generation += 1;
+            self.cache = Some(Cache {
+                params,
+                keep: VecDeque::new(),
+                rejected: 0,
+                last: None,
+            });
+        }
 
-        if height_delta < 1000 {
-            // Found a matching point within 1000ft, within 10s
-            matching += 1;
+        let cache = match self.cache.as_mut() {
+            Some(cache) => cache,
+            None => return,
+        };
 
-            if matching > 5 {
-                return true;
+        let ready = match live {
+            Some(now) => {
+                let window = params.algorithm().context();
+                points.partition_point(|point| point.time + window < now)
             }
+            None => points.len(),
+        };
+
+        if ready > cache.keep.len() {
+            let from = cache.keep.len();
+            let keep = params.algorithm().classify(points, from..ready);
+            cache.rejected += keep.iter().filter(|keep| !**keep).count();
+            cache.keep.extend(keep);
+            cache.last = Some(points[ready - 1].time);
         }
+
+        cache.keep.make_contiguous();
+    }
+
+    /// Whether each point was kept as of the last update, or `None` when no filter is applied or
+    /// previewed. It can be shorter than the points while live data is pending.
+    pub fn keep(&self) -> Option<&[bool]> {
+        // The last update left it contiguous
+        self.cache.as_ref().map(|cache| cache.keep.as_slices().0)
+    }
+
+    /// Like `keep`, but only for the applied parameters and not while previewing others.
+    pub fn applied_keep(&self) -> Option<&[bool]> {
+        if self.applied.is_some() && !self.preview {
+            self.keep()
+        } else {
+            None
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number of points rejected by the last update, without counting them every frame.
+    pub fn rejected(&self) -> Option<usize> {
+        self.cache.as_ref().map(|cache| cache.rejected)
+    }
+
+    /// Must be called when points are removed from the front of the live data.
+    pub fn prune(&mut self, count: usize) {
+        if let Some(cache) = &mut self.cache {
+            let count = count.min(cache.keep.len());
+            let pruned = cache.keep.drain(..count).filter(|keep| !*keep).count();
+            cache.rejected -= pruned;
+
+            if cache.keep.is_empty() {
+                cache.last = None;
+            }
+        }
+    }
+}
+
+/// Slides a time window along the points with two pointers, keeping the heights inside it sorted
+/// so the points within a height band can be found with a binary search.
+struct HeightWindow<'a> {
+    points: &'a [data::Point],
+    window: Duration,
+    // Sorted heights of points[start..end]
+    heights: Vec<u32>,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> HeightWindow<'a> {
+    fn new(points: &'a [data::Point], window: Duration, first: usize) -> Self {
+        let start = match points.get(first) {
+            Some(first) => points.partition_point(|point| point.time + window < first.time),
+            None => points.len(),
+        };
+
+        HeightWindow {
+            points,
+            window,
+            heights: vec![],
+            start,
+            end: start,
+        }
+    }
+
+    /// Moves the window to be centred on the given point, which must come after the previous one.
+    fn advance(&mut self, point: &data::Point) {
+        let points = self.points;
+
+        while self.end < points.len() && within_window(point, &points[self.end], self.window) {
+            if is_mode_ac(&points[self.end]) {
+                let height = points[self.end].height;
+                let index = self.heights.partition_point(|h| *h < height);
+                self.heights.insert(index, height);
+            }
+            self.end += 1;
+        }
+
+        while !within_window(point, &points[self.start], self.window) {
+            if is_mode_ac(&points[self.start]) {
+                let height = points[self.start].height;
+                let index = self.heights.partition_point(|h| *h < height);
+                self.heights.remove(index);
+            }
+            self.start += 1;
+        }
+    }
+
+    /// Sorted heights in the window between `low` and `high` inclusive.
+    fn between(&self, low: u32, high: u32) -> &[u32] {
+        let from = self.heights.partition_point(|h| *h < low);
+        let to = self.heights.partition_point(|h| *h <= high);
+        &self.heights[from..to.max(from)]
     }
 
-    false
+    /// Number of points in the window with the same time and height as the point, including itself.
+    fn duplicates(&self, point: &data::Point) -> usize {
+        let window = &self.points[self.start..self.end];
+        window[window.partition_point(|other| other.time < point.time)..]
+            .iter()
+            .take_while(|other| other.time == point.time)
+            .filter(|other| other.height == point.height && is_mode_ac(other))
+            .count()
+    }
+}
+
+/// Indices of the points within `width` either side of `time`.
+fn window_range(points: &[data::Point], time: SystemTime, width: Duration) -> Range<usize> {
+    let from = points.partition_point(|point| point.time + width < time);
+    let to = points.partition_point(|point| point.time <= time + width);
+    from..to.max(from)
+}
+
+/// Mode A/C replies, along with points from recordings made before the message was saved, which
+/// could only be Mode A/C.
+fn is_mode_ac(point: &data::Point) -> bool {
+    point.message.icao().is_none()
+}
+
+fn within_window(point: &data::Point, other: &data::Point, window: Duration) -> bool {
+    let delta = match point.time.duration_since(other.time) {
+        Ok(delta) => delta,
+        Err(e) => e.duration(),
+    };
+
+    delta <= window
 }
 
-fn time_diff(a: &SystemTime, b: &SystemTime) -> u64 {
-    let time_a = a.duration_since(UNIX_EPOCH).unwrap().as_secs();
-    let time_b = b.duration_since(UNIX_EPOCH).unwrap().as_secs();
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::store;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    pub fn point(secs: f64, height: u32) -> data::Point {
+        data::Point {
+            height,
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000) + Duration::from_secs_f64(secs),
+            message: data::Message::Unknown,
+            source: None,
+        }
+    }
+
+    // A steady trace every second with a garbled reply every tenth second
+    pub fn trace_with_noise(seconds: usize) -> Vec<data::Point> {
+        (0..seconds)
+            .flat_map(|second| {
+                let secs = second as f64;
+                let mut points = vec![point(secs, 40_000 + second as u32 * 5)];
+                if second % 10 == 5 {
+                    points.push(point(secs + 0.5, 5_000 + second as u32 * 97 % 60_000));
+                }
+                points
+            })
+            .collect()
+    }
+
+    /// A busy airliner climb, a lone U-2 replying every few seconds and garbled replies well
+    /// away from both, along with whether each point is real.
+    pub fn u2_with_garbles() -> (Vec<data::Point>, Vec<bool>) {
+        let mut points = vec![];
+        for tenth in 0..6000 {
+            let secs = f64::from(tenth) / 10.0;
+            if tenth % 5 == 0 {
+                points.push((point(secs, 30_000 + tenth), true));
+            }
+            if tenth % 30 == 0 {
+                points.push((point(secs, 65_000 + tenth % 7 * 20), true));
+            }
+            if tenth % 70 == 35 {
+                // Spread over the sky, skipping anywhere near the real traces
+                let height = tenth * 7919 % 90_000;
+                if height.abs_diff(65_000) > 3000 && !(27_000..40_000).contains(&height) {
+                    points.push((point(secs + 0.05, height), false));
+                }
+            }
+        }
+
+        points.into_iter().unzip()
+    }
+
+    /// Checks that every garbled reply is removed and nearly all of each trace is kept.
+    pub fn assert_separates(algorithm: &dyn Algorithm) {
+        let (points, real) = u2_with_garbles();
+        let keep = algorithm.classify(&points, 0..points.len());
+        assert_eq!(keep.len(), points.len());
+
+        let garbles = real.iter().filter(|real| !**real).count();
+        assert!(garbles > 50);
+        for (point, (keep, real)) in points.iter().zip(keep.iter().zip(&real)) {
+            if !real {
+                assert!(!keep, "Kept a garbled reply at {:}ft", point.height);
+            }
+        }
+
+        for (trace, heights) in [("airliner", 0..60_000), ("U-2", 60_000..70_000)] {
+            let (total, kept) = points
+                .iter()
+                .zip(keep.iter().zip(&real))
+                .filter(|(point, (_, real))| **real && heights.contains(&point.height))
+                .fold((0, 0), |(total, kept), (_, (keep, _))| {
+                    (total + 1, kept + usize::from(*keep))
+                });
+            assert!(
+                kept * 100 >= total * 95,
+                "Only kept {:} of {:} {:} points",
+                kept,
+                total,
+                trace
+            );
+        }
+    }
+
+    fn is_noise(point: &data::Point) -> bool {
+        point.height < 39_000
+    }
+
+    #[test]
+    fn undo_keeps_raw_points() {
+        let points = trace_with_noise(120);
+        let params = Params::default();
+        let mut filter = Filter::default();
 
-    time_a.abs_diff(time_b)
+        filter.update(&points, &params, None);
+        assert!(filter.keep().is_none());
+
+        filter.apply(params);
+        filter.update(&points, &params, None);
+        let keep = filter.keep().unwrap();
+        assert_eq!(keep.len(), points.len());
+        let noise = points.iter().filter(|point| is_noise(point)).count();
+        assert_eq!(filter.rejected(), Some(noise));
+        for (point, keep) in points.iter().zip(keep) {
+            assert_eq!(*keep, !is_noise(point));
+        }
+
+        filter.undo();
+        filter.update(&points, &params, None);
+        assert!(filter.keep().is_none());
+        assert_eq!(filter.rejected(), None);
+    }
+
+    #[test]
+    fn preview_uses_edited_params() {
+        let points = trace_with_noise(60);
+        let applied = Params::default();
+        let mut edited = applied;
+        edited.neighbours.min_neighbours = 1000;
+        let mut filter = Filter::default();
+
+        filter.apply(applied);
+        filter.update(&points, &edited, None);
+        let generation = filter.generation();
+        assert_eq!(filter.rejected(), Some(6));
+
+        filter.preview = true;
+        filter.update(&points, &edited, None);
+        assert_eq!(filter.rejected(), Some(points.len()));
+        assert_ne!(filter.generation(), generation);
+    }
+
+    fn batch(points: &[data::Point], params: &Params) -> Vec<bool> {
+        let mut filter = Filter::default();
+        filter.apply(*params);
+        filter.update(points, params, None);
+        filter.keep().unwrap().to_vec()
+    }
+
+    #[test]
+    fn live_matches_batch() {
+        let points = trace_with_noise(300);
+
+        for kind in Kind::ALL {
+            let params = Params {
+                kind,
+                ..Params::default()
+            };
+            let expected = batch(&points, &params);
+            let context = params.algorithm().context();
+            let mut filter = Filter::default();
+            filter.apply(params);
+
+            // Points arrive in batches, and each is only classified once its window has passed
+            for end in (0..=points.len()).step_by(7) {
+                let now = points[end.max(1) - 1].time;
+                filter.update(&points[..end], &params, Some(now));
+                let keep = filter.keep().unwrap();
+                assert!(keep
+                    .iter()
+                    .zip(&points)
+                    .all(|(_, point)| point.time + context < now));
+                assert_eq!(keep, &expected[..keep.len()], "{:?}", kind);
+            }
+
+            let later = points.last().unwrap().time + context * 2;
+            filter.update(&points, &params, Some(later));
+            assert_eq!(filter.keep().unwrap(), expected, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn pruning_keeps_classification_aligned() {
+        let points = trace_with_noise(200);
+        let params = Params::default();
+        let expected = batch(&points, &params);
+        let mut filter = Filter::default();
+        filter.apply(params);
+
+        let later = points.last().unwrap().time + Duration::from_secs(60);
+        filter.update(&points, &params, Some(later));
+        filter.prune(50);
+        filter.update(&points[50..], &params, Some(later));
+
+        assert_eq!(filter.keep().unwrap(), &expected[50..]);
+        let rejected = expected[50..].iter().filter(|keep| !**keep).count();
+        assert_eq!(filter.rejected(), Some(rejected));
+    }
+
+    #[test]
+    fn loading_older_points_reclassifies() {
+        // An autosave is preloaded and classified, then an older recording is loaded over it
+        let points = trace_with_noise(300);
+        let params = Params::default();
+        let mut store = store::Store::default();
+        store.extend(points[150..].to_vec());
+        let mut filter = Filter::default();
+        filter.apply(params);
+        filter.update(store.as_slice(), &params, None);
+        let generation = filter.generation();
+
+        store.extend(points[..150].to_vec());
+        filter.restart();
+        filter.update(store.as_slice(), &params, None);
+
+        assert_ne!(filter.generation(), generation);
+        assert_eq!(filter.keep().unwrap(), batch(&points, &params));
+        for (point, keep) in store.as_slice().iter().zip(filter.applied_keep().unwrap()) {
+            assert_eq!(*keep, !is_noise(point));
+        }
+    }
+
+    #[test]
+    fn mode_s_points_are_kept_and_ignored() {
+        let mode_ac = trace_with_noise(300);
+        // Aircraft reporting near the garbled replies would otherwise vouch for them
+        let mut points = mode_ac.clone();
+        for (index, point) in mode_ac.iter().enumerate().filter(|(_, p)| is_noise(p)) {
+            for offset in 0..8 {
+                let mut mode_s = point.clone();
+                mode_s.time += Duration::from_millis(offset * 100 + 1);
+                mode_s.message = data::Message::ModeS { icao: index as u32 };
+                points.push(mode_s);
+            }
+        }
+        points.sort_by_key(|point| point.time);
+
+        for kind in Kind::ALL {
+            let params = Params {
+                kind,
+                ..Params::default()
+            };
+            let expected = batch(&mode_ac, &params);
+            let keep = batch(&points, &params);
+
+            let (mode_s, kept): (Vec<_>, Vec<_>) = points
+                .iter()
+                .zip(keep)
+                .partition(|(point, _)| point.message.icao().is_some());
+            assert!(mode_s.iter().all(|(_, keep)| *keep), "{:?}", kind);
+            let kept: Vec<bool> = kept.into_iter().map(|(_, keep)| keep).collect();
+            assert_eq!(kept, expected, "{:?}", kind);
+        }
+    }
 }
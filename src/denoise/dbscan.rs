@@ -0,0 +1,150 @@
+use super::{is_mode_ac, window_range, Algorithm};
+use crate::{data, vrate};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::time::Duration;
+
+/// Density clustering over time and height. Points are neighbours when they are within an
+/// ellipse of `eps_secs` by `eps_height` feet. A point with at least `min_points` neighbours,
+/// counting itself, is a core point. Every point that is a core point or a neighbour of one is
+/// part of a cluster and kept, everything else is noise.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Dbscan {
+    pub eps_secs: f64,
+    pub eps_height: u32,
+    pub min_points: usize,
+}
+
+impl Default for Dbscan {
+    fn default() -> Self {
+        Dbscan {
+            eps_secs: 15.0,
+            eps_height: 500,
+            min_points: 4,
+        }
+    }
+}
+
+impl Dbscan {
+    fn eps(&self) -> Duration {
+        Duration::from_secs_f64(self.eps_secs)
+    }
+
+    /// Indices of the neighbours of a point, including the point itself.
+    fn neighbours<'a>(
+        &self,
+        points: &'a [data::Point],
+        point: &'a data::Point,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let eps_secs = self.eps_secs.max(0.001);
+        let eps_height = f64::from(self.eps_height.max(1));
+
+        window_range(points, point.time, self.eps()).filter(move |index| {
+            let other = &points[*index];
+            if !is_mode_ac(other) {
+                return false;
+            }
+            let dt = vrate::seconds_between(point.time, other.time) / eps_secs;
+            let dh = (f64::from(other.height) - f64::from(point.height)) / eps_height;
+            dt * dt + dh * dh <= 1.0
+        })
+    }
+}
+
+impl Algorithm for Dbscan {
+    // Deciding if a neighbour is a core point needs its neighbours too
+    fn context(&self) -> Duration {
+        self.eps() * 2
+    }
+
+    fn classify(&self, points: &[data::Point], range: Range<usize>) -> Vec<bool> {
+        let (first, last) = match (points.get(range.start), range.end.checked_sub(1)) {
+            (Some(first), Some(last)) if !range.is_empty() => (first, &points[last]),
+            _ => return vec![],
+        };
+
+        // Work out which points are core points once, for every point that could be a neighbour
+        let eps = self.eps();
+        let from = points.partition_point(|point| point.time + eps < first.time);
+        let to = points.partition_point(|point| point.time <= last.time + eps);
+        let core: Vec<bool> = points[from..to]
+            .iter()
+            .map(|point| self.neighbours(points, point).count() >= self.min_points)
+            .collect();
+
+        points[range]
+            .iter()
+            .map(|point| {
+                !is_mode_ac(point)
+                    || self
+                        .neighbours(points, point)
+                        .any(|index| core[index - from])
+            })
+            .collect()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let secs_fmt = |x, _| format!("{:.1} s", x);
+        let ft_fmt = |x, _| format!("{:.0} ft", x);
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.eps_secs, 1.0..=60.0)
+                    .custom_formatter(secs_fmt)
+                    .text("Time radius"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.eps_height, 50..=5000)
+                    .custom_formatter(ft_fmt)
+                    .text("Height radius"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.min_points, 1..=50).text("Min points"));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::{assert_separates, point};
+
+    #[test]
+    fn keeps_sparse_trace_and_removes_garbles() {
+        assert_separates(&Dbscan::default());
+    }
+
+    #[test]
+    fn removes_small_bursts() {
+        let mut points: Vec<data::Point> = (0..20)
+            .map(|second| point(f64::from(second), 45_000))
+            .collect();
+        // Fewer than min_points together is not a cluster
+        points.push(point(20.0, 20_000));
+        points.push(point(20.5, 20_100));
+        points.push(point(21.0, 20_050));
+
+        let keep = Dbscan::default().classify(&points, 0..points.len());
+        assert_eq!(keep, [vec![true; 20], vec![false; 3]].concat());
+    }
+
+    #[test]
+    fn edge_of_a_cluster_is_kept() {
+        let points: Vec<data::Point> = (0..10)
+            .map(|second| point(f64::from(second), 45_000))
+            .chain([point(20.0, 45_000)])
+            .collect();
+        let dbscan = Dbscan {
+            eps_secs: 11.0,
+            ..Dbscan::default()
+        };
+
+        // The last point only has two neighbours, but one of them is a core point
+        assert!(dbscan.classify(&points, 0..points.len())[10]);
+    }
+}
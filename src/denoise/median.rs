@@ -0,0 +1,116 @@
+use super::{is_mode_ac, Algorithm, HeightWindow};
+use crate::data;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::time::Duration;
+
+/// Takes the median height of the points within `window_secs` and `search_band` feet of each
+/// point, and keeps the point if it is within `tolerance` feet of that median. Points with fewer
+/// than `min_points` others around them are rejected.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct RollingMedian {
+    pub window_secs: f64,
+    pub search_band: u32,
+    pub tolerance: u32,
+    pub min_points: usize,
+}
+
+impl Default for RollingMedian {
+    fn default() -> Self {
+        RollingMedian {
+            window_secs: 20.0,
+            search_band: 2000,
+            tolerance: 300,
+            min_points: 2,
+        }
+    }
+}
+
+impl Algorithm for RollingMedian {
+    fn context(&self) -> Duration {
+        Duration::from_secs_f64(self.window_secs)
+    }
+
+    fn classify(&self, points: &[data::Point], range: Range<usize>) -> Vec<bool> {
+        let mut window = HeightWindow::new(points, self.context(), range.start);
+
+        points[range]
+            .iter()
+            .map(|point| {
+                window.advance(point);
+                if !is_mode_ac(point) {
+                    return true;
+                }
+
+                let nearby = window.between(
+                    point.height.saturating_sub(self.search_band),
+                    point.height.saturating_add(self.search_band),
+                );
+
+                // The point itself is always in the window
+                if nearby.len() <= self.min_points {
+                    return false;
+                }
+
+                let median = nearby[nearby.len() / 2];
+                median.abs_diff(point.height) <= self.tolerance
+            })
+            .collect()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let secs_fmt = |x, _| format!("{:.1} s", x);
+        let ft_fmt = |x, _| format!("{:.0} ft", x);
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.window_secs, 1.0..=120.0)
+                    .custom_formatter(secs_fmt)
+                    .text("Window"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.search_band, 100..=10_000)
+                    .custom_formatter(ft_fmt)
+                    .text("Search band"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.tolerance, 0..=2000)
+                    .custom_formatter(ft_fmt)
+                    .text("Tolerance"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.min_points, 0..=50).text("Min points"));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::{assert_separates, point};
+
+    #[test]
+    fn keeps_sparse_trace_and_removes_garbles() {
+        assert_separates(&RollingMedian::default());
+    }
+
+    #[test]
+    fn removes_spikes_next_to_a_trace() {
+        // A reply 800ft off a level trace is near enough to be compared, but not on the median
+        let mut points: Vec<data::Point> = (0..20)
+            .map(|second| point(f64::from(second), 45_000))
+            .collect();
+        points.insert(10, point(9.5, 45_800));
+
+        let keep = RollingMedian::default().classify(&points, 0..points.len());
+        assert!(!keep[10]);
+        assert_eq!(keep.iter().filter(|keep| **keep).count(), 20);
+    }
+}
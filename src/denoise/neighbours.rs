@@ -0,0 +1,189 @@
+use super::{is_mode_ac, Algorithm, HeightWindow};
+use crate::data;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::time::Duration;
+
+/// A point is kept when more than `min_neighbours` other points are within `window_secs` and
+/// `height_band` feet of it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Neighbours {
+    pub window_secs: f64,
+    pub height_band: u32,
+    pub min_neighbours: usize,
+}
+
+impl Default for Neighbours {
+    fn default() -> Self {
+        Neighbours {
+            window_secs: 10.0,
+            height_band: 1000,
+            min_neighbours: 5,
+        }
+    }
+}
+
+impl Algorithm for Neighbours {
+    fn context(&self) -> Duration {
+        Duration::from_secs_f64(self.window_secs)
+    }
+
+    fn classify(&self, points: &[data::Point], range: Range<usize>) -> Vec<bool> {
+        let band = self.height_band.max(1);
+        let mut window = HeightWindow::new(points, self.context(), range.start);
+
+        points[range]
+            .iter()
+            .map(|point| {
+                window.advance(point);
+                if !is_mode_ac(point) {
+                    return true;
+                }
+
+                let nearby = window
+                    .between(
+                        point.height.saturating_sub(band - 1),
+                        point.height.saturating_add(band - 1),
+                    )
+                    .len();
+
+                // Duplicates of this point (including itself) are not neighbours
+                nearby - window.duplicates(point) > self.min_neighbours
+            })
+            .collect()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let secs_fmt = |x, _| format!("{:.1} s", x);
+        let ft_fmt = |x, _| format!("{:.0} ft", x);
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.window_secs, 1.0..=60.0)
+                    .custom_formatter(secs_fmt)
+                    .text("Window"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.height_band, 100..=5000)
+                    .custom_formatter(ft_fmt)
+                    .text("Height band"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.min_neighbours, 0..=50).text("Min neighbours"));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::point;
+    use crate::vrate;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // The rule from the original denoise, checking every other point: neighbours are within the
+    // window and less than the height band away, and exact duplicates don't count. Unlike the
+    // original it compares times to the sub-second rather than in whole seconds.
+    fn brute_force(neighbours: &Neighbours, points: &[data::Point]) -> Vec<bool> {
+        points
+            .iter()
+            .map(|point| {
+                let count = points
+                    .iter()
+                    .filter(|other| other.time != point.time || other.height != point.height)
+                    .filter(|other| {
+                        vrate::seconds_between(point.time, other.time).abs()
+                            <= neighbours.window_secs
+                    })
+                    .filter(|other| other.height.abs_diff(point.height) < neighbours.height_band)
+                    .count();
+                count > neighbours.min_neighbours
+            })
+            .collect()
+    }
+
+    fn random_points(seed: u64, count: usize) -> Vec<data::Point> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut secs = 0.0;
+        (0..count)
+            .map(|_| {
+                secs += rng.gen_range(0.0..1.5);
+                // Mostly a few traces, with some garbled replies anywhere
+                let height = if rng.gen_bool(0.8) {
+                    [12_000, 35_000, 62_000][rng.gen_range(0..3)] + rng.gen_range(0..1500)
+                } else {
+                    rng.gen_range(0..100_000)
+                };
+                point(secs, height)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        for seed in 0..5 {
+            let points = random_points(seed, 2000);
+            for neighbours in [
+                Neighbours::default(),
+                Neighbours {
+                    window_secs: 2.5,
+                    height_band: 300,
+                    min_neighbours: 1,
+                },
+            ] {
+                let expected = brute_force(&neighbours, &points);
+                assert_eq!(neighbours.classify(&points, 0..points.len()), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn sub_second_window() {
+        // Whole seconds would put all of these in the same second
+        let points = vec![
+            point(0.0, 30_000),
+            point(0.4, 30_100),
+            point(0.8, 30_200),
+            point(1.7, 30_300),
+        ];
+        let neighbours = Neighbours {
+            window_secs: 0.5,
+            height_band: 1000,
+            min_neighbours: 0,
+        };
+
+        assert_eq!(
+            neighbours.classify(&points, 0..points.len()),
+            vec![true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn duplicates_are_not_neighbours() {
+        let points = vec![point(0.0, 30_000); 10];
+        let neighbours = Neighbours {
+            min_neighbours: 0,
+            ..Neighbours::default()
+        };
+
+        assert!(neighbours
+            .classify(&points, 0..points.len())
+            .iter()
+            .all(|keep| !keep));
+    }
+
+    #[test]
+    fn classifies_part_of_the_points() {
+        let points = random_points(7, 500);
+        let neighbours = Neighbours::default();
+        let all = neighbours.classify(&points, 0..points.len());
+
+        assert_eq!(neighbours.classify(&points, 100..300), &all[100..300]);
+    }
+}
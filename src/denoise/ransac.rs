@@ -0,0 +1,249 @@
+use super::{is_mode_ac, Algorithm};
+use crate::data;
+use eframe::egui;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::time::{Duration, UNIX_EPOCH};
+
+// Nothing we plot climbs or descends faster than this, so lines steeper than it are not tried
+const MAX_RATE_FT_PER_SEC: f64 = 10_000.0 / 60.0;
+// How far apart in height the two points picked for a candidate line can be
+const SAMPLE_BAND: u32 = 2000;
+const MAX_LINES: usize = 64;
+
+/// Splits the points into fixed segments of `segment_secs`, then repeatedly fits straight lines
+/// through each segment with RANSAC so that every trace is approximated piecewise-linearly.
+/// Points within `tolerance` feet of a line supported by at least `min_inliers` points are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Ransac {
+    pub segment_secs: f64,
+    pub tolerance: u32,
+    pub min_inliers: usize,
+    pub iterations: usize,
+}
+
+impl Default for Ransac {
+    fn default() -> Self {
+        Ransac {
+            segment_secs: 60.0,
+            tolerance: 250,
+            min_inliers: 5,
+            iterations: 100,
+        }
+    }
+}
+
+impl Ransac {
+    /// Returns whether each point of a segment lies on one of its lines.
+    fn fit_segment(&self, points: &[data::Point], start: f64, seed: u64) -> Vec<bool> {
+        let segment = Segment::new(points, start);
+        let tolerance = f64::from(self.tolerance);
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Mode S points start out kept, which also keeps them off every line
+        let mut keep: Vec<bool> = points.iter().map(|point| !is_mode_ac(point)).collect();
+
+        for _ in 0..MAX_LINES {
+            let unassigned: Vec<usize> = (0..points.len()).filter(|i| !keep[*i]).collect();
+            if unassigned.len() < self.min_inliers.max(2) {
+                break;
+            }
+
+            let mut best: Option<(usize, f64, f64)> = None;
+            for _ in 0..self.iterations {
+                let a = unassigned[rng.gen_range(0..unassigned.len())];
+                let (xa, ya) = segment.samples[a];
+
+                // The point itself is always within the band
+                let near =
+                    segment.between(ya - f64::from(SAMPLE_BAND), ya + f64::from(SAMPLE_BAND));
+                let b = near[rng.gen_range(0..near.len())];
+                let (xb, yb) = segment.samples[b];
+
+                if keep[b] || (xb - xa).abs() < 0.01 {
+                    continue;
+                }
+
+                let slope = (yb - ya) / (xb - xa);
+                if slope.abs() > MAX_RATE_FT_PER_SEC {
+                    continue;
+                }
+                let intercept = ya - slope * xa;
+
+                let count = segment
+                    .inliers(&keep, slope, intercept, tolerance, self.segment_secs)
+                    .count();
+                if best.map_or(true, |(best, _, _)| count > best) {
+                    best = Some((count, slope, intercept));
+                }
+            }
+
+            match best {
+                Some((count, slope, intercept)) if count >= self.min_inliers => {
+                    let inliers: Vec<usize> = segment
+                        .inliers(&keep, slope, intercept, tolerance, self.segment_secs)
+                        .collect();
+                    for index in inliers {
+                        keep[index] = true;
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        keep
+    }
+}
+
+struct Segment {
+    // Seconds since the start of the segment and height of each point
+    samples: Vec<(f64, f64)>,
+    // Indices ordered by height, so only the points near a line need to be checked against it
+    by_height: Vec<usize>,
+    heights: Vec<f64>,
+}
+
+impl Segment {
+    fn new(points: &[data::Point], start: f64) -> Self {
+        let samples: Vec<(f64, f64)> = points
+            .iter()
+            .map(|point| (seconds(point) - start, f64::from(point.height)))
+            .collect();
+
+        let mut by_height: Vec<usize> = (0..points.len()).collect();
+        by_height.sort_by_key(|index| points[*index].height);
+        let heights = by_height.iter().map(|index| samples[*index].1).collect();
+
+        Segment {
+            samples,
+            by_height,
+            heights,
+        }
+    }
+
+    fn between(&self, low: f64, high: f64) -> &[usize] {
+        let from = self.heights.partition_point(|height| *height < low);
+        let to = self.heights.partition_point(|height| *height <= high);
+        &self.by_height[from..to.max(from)]
+    }
+
+    fn inliers<'a>(
+        &'a self,
+        keep: &'a [bool],
+        slope: f64,
+        intercept: f64,
+        tolerance: f64,
+        length: f64,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let start = intercept;
+        let end = intercept + slope * length;
+
+        self.between(start.min(end) - tolerance, start.max(end) + tolerance)
+            .iter()
+            .copied()
+            .filter(move |index| {
+                let (x, y) = self.samples[*index];
+                !keep[*index] && (y - (slope * x + intercept)).abs() <= tolerance
+            })
+    }
+}
+
+impl Algorithm for Ransac {
+    // A point can only be classified once the rest of its segment has arrived
+    fn context(&self) -> Duration {
+        Duration::from_secs_f64(self.segment_secs)
+    }
+
+    fn classify(&self, points: &[data::Point], range: Range<usize>) -> Vec<bool> {
+        let segment_secs = self.segment_secs.max(1.0);
+        let mut out = Vec::with_capacity(range.len());
+        let mut index = range.start;
+
+        while index < range.end {
+            // Segments are aligned to the epoch, so they are the same however the points are split
+            let segment = (seconds(&points[index]) / segment_secs).floor();
+            let start = segment * segment_secs;
+            let end = start + segment_secs;
+
+            let from = points.partition_point(|point| seconds(point) < start);
+            let to = points.partition_point(|point| seconds(point) < end);
+
+            let keep = self.fit_segment(&points[from..to], start, segment as u64);
+            let until = to.min(range.end);
+            out.extend_from_slice(&keep[index - from..until - from]);
+            index = until;
+        }
+
+        out
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let secs_fmt = |x, _| format!("{:.0} s", x);
+        let ft_fmt = |x, _| format!("{:.0} ft", x);
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.segment_secs, 10.0..=300.0)
+                    .custom_formatter(secs_fmt)
+                    .text("Segment length"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.tolerance, 50..=2000)
+                    .custom_formatter(ft_fmt)
+                    .text("Tolerance"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.min_inliers, 2..=50).text("Min inliers"));
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.iterations, 10..=1000).text("Iterations"));
+        });
+    }
+}
+
+fn seconds(point: &data::Point) -> f64 {
+    point
+        .time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |duration| duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::{assert_separates, point};
+
+    #[test]
+    fn keeps_sparse_trace_and_removes_garbles() {
+        assert_separates(&Ransac::default());
+    }
+
+    #[test]
+    fn same_result_however_the_points_are_split() {
+        let points: Vec<data::Point> = (0..600)
+            .map(|tenth| {
+                let height = if tenth % 13 == 0 {
+                    tenth * 7919 % 40_000
+                } else {
+                    50_000 - tenth * 2
+                };
+                point(f64::from(tenth) / 4.0, height)
+            })
+            .collect();
+        let ransac = Ransac::default();
+        let all = ransac.classify(&points, 0..points.len());
+
+        let split = [
+            ransac.classify(&points, 0..250),
+            ransac.classify(&points, 250..points.len()),
+        ]
+        .concat();
+        assert_eq!(split, all);
+        assert!(all.iter().filter(|keep| !**keep).count() >= 40);
+    }
+}
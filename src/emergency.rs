@@ -0,0 +1,126 @@
+use crate::{data, store, units, view};
+use eframe::egui;
+use std::time::{Duration, SystemTime};
+use thousands::Separable;
+
+pub const SQUAWKS: [u16; 3] = [7500, 7600, 7700];
+// Replies further apart than this are separate events
+const EVENT_GAP: Duration = Duration::from_secs(5 * 60);
+// Radars interrogate Mode A and Mode C alternately, so the same aircraft's altitude reply should
+// be very close in time to its identity reply
+const CORRELATION_WINDOW: Duration = Duration::from_millis(100);
+const EMERGENCY_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 80, 80);
+
+/// A Mode A identity reply with an emergency squawk.
+pub struct Reply {
+    pub squawk: u16,
+    pub time: SystemTime,
+}
+
+struct Event {
+    squawk: u16,
+    first: SystemTime,
+    last: SystemTime,
+    count: usize,
+    // From the Mode C reply nearest to the latest identity reply, when there was one
+    altitude: Option<u32>,
+    // Latest reply still waiting for altitude replies around it to arrive
+    pending: Option<SystemTime>,
+}
+
+#[derive(Default)]
+pub struct Events {
+    events: Vec<Event>,
+    pub open: bool,
+}
+
+impl Events {
+    pub fn push(&mut self, reply: Reply) {
+        let existing = self
+            .events
+            .iter_mut()
+            .rev()
+            .find(|event| event.squawk == reply.squawk && event.last + EVENT_GAP >= reply.time);
+
+        match existing {
+            Some(event) => {
+                event.last = reply.time;
+                event.count += 1;
+                event.pending = Some(reply.time);
+            }
+            None => {
+                println!("Emergency squawk {:}", reply.squawk);
+                self.events.push(Event {
+                    squawk: reply.squawk,
+                    first: reply.time,
+                    last: reply.time,
+                    count: 1,
+                    altitude: None,
+                    pending: Some(reply.time),
+                });
+            }
+        }
+    }
+
+    /// Looks for altitude replies around any new identity replies.
+    pub fn correlate(&mut self, points: &[data::Point], now: SystemTime) {
+        for event in &mut self.events {
+            let time = match event.pending {
+                Some(time) if time + CORRELATION_WINDOW < now => time,
+                _ => continue,
+            };
+            event.pending = None;
+
+            let range = store::range(points, time - CORRELATION_WINDOW, time + CORRELATION_WINDOW);
+            // Mode S aircraft report their own altitude, so only Mode C replies are candidates
+            let nearest = points[range]
+                .iter()
+                .filter(|point| point.message.icao().is_none())
+                .min_by_key(|point| match point.time.duration_since(time) {
+                    Ok(delta) => delta,
+                    Err(e) => e.duration(),
+                });
+            if let Some(point) = nearest {
+                event.altitude = Some(point.height);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn ui(&mut self, ctx: &egui::Context, utc: bool, converter: units::Converter) {
+        let events = &self.events;
+        egui::Window::new("Emergencies")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                egui::Grid::new("Emergency events")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Squawk");
+                        ui.label("First");
+                        ui.label("Last");
+                        ui.label("Replies");
+                        ui.label("Altitude");
+                        ui.end_row();
+
+                        for event in events.iter().rev() {
+                            ui.colored_label(EMERGENCY_COLOR, format!("{:04}", event.squawk));
+                            ui.label(view::format_timestamp(event.first, utc));
+                            ui.label(view::format_timestamp(event.last, utc));
+                            ui.label(event.count.separate_with_commas());
+                            ui.label(event.altitude.map_or_else(
+                                || "Unknown".to_owned(),
+                                |altitude| converter.format(f64::from(altitude)),
+                            ));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
@@ -0,0 +1,161 @@
+use crate::adsb;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+// A GNSS height is compared against the barometric altitude nearest in time, as long as it is
+// this close. Position squitters alternate with surveillance replies a few times a second.
+const PAIR_WINDOW: Duration = Duration::from_secs(1);
+
+/// Barometric and GNSS altitudes from one aircraft, in feet.
+#[derive(Default)]
+pub struct Track {
+    pub barometric: VecDeque<(SystemTime, u32)>,
+    pub gnss: VecDeque<(SystemTime, u32)>,
+    // GNSS minus barometric, as reported in velocity messages or worked out from the two series
+    pub difference: VecDeque<(SystemTime, i32)>,
+    // Most aircraft never report a GNSS height, so until one does only enough barometric history
+    // to pair with it is kept
+    reports_gnss: bool,
+}
+
+impl Track {
+    fn nearest_barometric(&self, time: SystemTime) -> Option<u32> {
+        let index = self
+            .barometric
+            .partition_point(|(barometric, _)| *barometric < time);
+        [index.checked_sub(1), Some(index)]
+            .into_iter()
+            .flatten()
+            .filter_map(|index| self.barometric.get(index))
+            .map(|(barometric, height)| {
+                let apart = match barometric.duration_since(time) {
+                    Ok(apart) => apart,
+                    Err(e) => e.duration(),
+                };
+                (apart, *height)
+            })
+            .filter(|(apart, _)| *apart <= PAIR_WINDOW)
+            .min_by_key(|(apart, _)| *apart)
+            .map(|(_, height)| height)
+    }
+
+    // Whether the aircraft has reported both kinds of altitude
+    fn is_comparable(&self) -> bool {
+        !self.barometric.is_empty() && !self.difference.is_empty()
+    }
+}
+
+/// Collects both kinds of altitude from aircraft that report them, to study how far pressure
+/// altitude is from the true height.
+#[derive(Default)]
+pub struct Comparison {
+    tracks: BTreeMap<u32, Track>,
+}
+
+impl Comparison {
+    pub fn push(&mut self, report: &adsb::Report) {
+        let time = report.time;
+        match report.kind {
+            adsb::ReportKind::Altitude(height) => {
+                let track = self.tracks.entry(report.icao).or_default();
+                track.barometric.push_back((time, height));
+                if !track.reports_gnss {
+                    if let Some(cutoff) = time.checked_sub(PAIR_WINDOW) {
+                        let count = track.barometric.partition_point(|(time, _)| *time < cutoff);
+                        track.barometric.drain(..count);
+                    }
+                }
+            }
+            adsb::ReportKind::GnssHeight(height) => {
+                let track = self.tracks.entry(report.icao).or_default();
+                track.reports_gnss = true;
+                track.gnss.push_back((time, height));
+                if let Some(barometric) = track.nearest_barometric(time) {
+                    let difference = i64::from(height) - i64::from(barometric);
+                    track
+                        .difference
+                        .push_back((time, i32::try_from(difference).unwrap_or(i32::MAX)));
+                }
+            }
+            adsb::ReportKind::GnssDifference(difference) => {
+                let track = self.tracks.entry(report.icao).or_default();
+                track.reports_gnss = true;
+                track.difference.push_back((time, difference));
+                if let Some(barometric) = track.nearest_barometric(time) {
+                    let height = i64::from(barometric) + i64::from(difference);
+                    if let Ok(height) = u32::try_from(height) {
+                        track.gnss.push_back((time, height));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn prune(&mut self, cutoff: SystemTime) {
+        for track in self.tracks.values_mut() {
+            let count = track.barometric.partition_point(|(time, _)| *time < cutoff);
+            track.barometric.drain(..count);
+            let count = track.gnss.partition_point(|(time, _)| *time < cutoff);
+            track.gnss.drain(..count);
+            let count = track.difference.partition_point(|(time, _)| *time < cutoff);
+            track.difference.drain(..count);
+        }
+        self.tracks.retain(|_, track| !track.barometric.is_empty());
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+
+    /// Aircraft that have reported both kinds of altitude.
+    pub fn tracks(&self) -> impl Iterator<Item = (u32, &Track)> {
+        self.tracks
+            .iter()
+            .filter(|(_, track)| track.is_comparable())
+            .map(|(icao, track)| (*icao, track))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn report(icao: u32, millis: u64, kind: adsb::ReportKind) -> adsb::Report {
+        adsb::Report {
+            icao,
+            verified: true,
+            time: UNIX_EPOCH + Duration::from_millis(millis),
+            kind,
+        }
+    }
+
+    #[test]
+    fn only_keeps_barometric_history_for_gnss_aircraft() {
+        let mut comparison = Comparison::default();
+        for second in 0..60 {
+            let millis = second * 1000;
+            comparison.push(&report(1, millis, adsb::ReportKind::Altitude(30_000)));
+            comparison.push(&report(2, millis, adsb::ReportKind::Altitude(35_000)));
+            if second == 10 {
+                comparison.push(&report(
+                    2,
+                    millis + 200,
+                    adsb::ReportKind::GnssDifference(-300),
+                ));
+            }
+        }
+
+        assert!(comparison.tracks[&1].barometric.len() <= 2);
+        // Everything from just before the first GNSS report
+        assert_eq!(comparison.tracks[&2].barometric.len(), 51);
+
+        let tracks: Vec<(u32, &Track)> = comparison.tracks().collect();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(
+            tracks[0].1.gnss.back().map(|(_, height)| *height),
+            Some(34_700)
+        );
+    }
+}
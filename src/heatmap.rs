@@ -0,0 +1,391 @@
+use crate::{store, view};
+use eframe::egui;
+use eframe::egui::plot::{PlotImage, PlotPoint, PlotUi};
+use eframe::egui::{Color32, ColorImage, TextureHandle, TextureOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Bin sizes that can be picked, in seconds and feet
+const TIME_BINS: [u32; 7] = [5, 15, 30, 60, 300, 900, 3600];
+const HEIGHT_BINS: [u32; 6] = [100, 200, 500, 1000, 2000, 5000];
+// Bins are merged to keep the image within the smallest texture size GPUs have to support
+const MAX_BINS: i64 = 2048;
+// From the quietest to the busiest bin
+const COLOR_MAP: [Color32; 5] = [
+    Color32::from_rgb(68, 1, 84),
+    Color32::from_rgb(59, 82, 139),
+    Color32::from_rgb(33, 145, 140),
+    Color32::from_rgb(94, 201, 98),
+    Color32::from_rgb(253, 231, 37),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Params {
+    pub time_bin: u32,
+    pub height_bin: u32,
+    pub log_scale: bool,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            time_bin: 60,
+            height_bin: 500,
+            log_scale: true,
+        }
+    }
+}
+
+impl Params {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Time bin")
+            .selected_text(format_seconds(self.time_bin))
+            .show_ui(ui, |ui| {
+                for bin in TIME_BINS {
+                    ui.selectable_value(&mut self.time_bin, bin, format_seconds(bin));
+                }
+            });
+        egui::ComboBox::from_label("Height bin")
+            .selected_text(format!("{:} ft", self.height_bin))
+            .show_ui(ui, |ui| {
+                for bin in HEIGHT_BINS {
+                    ui.selectable_value(&mut self.height_bin, bin, format!("{:} ft", bin));
+                }
+            });
+        ui.checkbox(&mut self.log_scale, "Log colour scale");
+    }
+}
+
+// Everything that, when changed, means the points need counting again from scratch
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Key {
+    time_bin: u32,
+    height_bin: u32,
+    store_generation: u64,
+    filter_generation: u64,
+}
+
+// The bins the texture was last drawn from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Frame {
+    columns: (i64, i64),
+    rows: (i64, i64),
+    log_scale: bool,
+}
+
+/// Counts of replies in time × height bins, drawn as a colour mapped image.
+///
+/// Like the point decimation, bins are aligned to the unix epoch so they stay valid as live
+/// data arrives and only new points need counting each frame.
+#[derive(Default)]
+pub struct Heatmap {
+    key: Option<Key>,
+    // Replies in each height bin, for each time bin since the unix epoch
+    columns: BTreeMap<i64, Vec<u32>>,
+    // The most height bins any column has
+    rows: usize,
+    binned_until: Option<SystemTime>,
+    oldest: Option<SystemTime>,
+    texture: Option<TextureHandle>,
+    drawn: Option<Frame>,
+}
+
+impl Heatmap {
+    /// Counts any new points. `keep` is the denoise filter, points rejected by it are not counted
+    /// and points past its end are not counted until they have been classified.
+    pub fn update(
+        &mut self,
+        store: &store::Store,
+        keep: Option<&[bool]>,
+        filter_generation: u64,
+        params: &Params,
+    ) {
+        let key = Key {
+            time_bin: params.time_bin.max(1),
+            height_bin: params.height_bin.max(1),
+            store_generation: store.generation(),
+            filter_generation,
+        };
+        if self.key != Some(key) {
+            self.key = Some(key);
+            self.columns.clear();
+            self.rows = 0;
+            self.binned_until = None;
+            self.oldest = None;
+            self.drawn = None;
+        }
+
+        let points = store.as_slice();
+        let kept = |index: usize| keep.map_or(true, |keep| keep[index]);
+        let from = self.binned_until.map_or(0, |until| {
+            points.partition_point(|point| point.time <= until)
+        });
+        let to = keep.map_or(points.len(), |keep| keep.len().min(points.len()));
+
+        // Points pruned from the front can't be taken back off their counts, so columns are only
+        // dropped once they are entirely older than the data. The oldest column keeps counting
+        // the pruned part of its bin, rather than being counted again every time it shrinks.
+        let oldest = points.first().map(|point| point.time);
+        if oldest != self.oldest && self.binned_until.is_some() {
+            self.oldest = oldest;
+
+            let first = oldest.map_or(i64::MAX, |time| time_column(time, key.time_bin));
+            if self
+                .columns
+                .keys()
+                .next()
+                .map_or(false, |column| *column < first)
+            {
+                self.columns = self.columns.split_off(&first);
+                self.drawn = None;
+            }
+        }
+
+        if to > from {
+            for (index, point) in points.iter().enumerate().take(to).skip(from) {
+                if kept(index) {
+                    self.count(point.time, point.height, key);
+                }
+            }
+            self.oldest = oldest;
+            self.binned_until = Some(points[to - 1].time);
+            self.drawn = None;
+        }
+    }
+
+    fn count(&mut self, time: SystemTime, height: u32, key: Key) {
+        let row = (height / key.height_bin) as usize;
+        let column = self
+            .columns
+            .entry(time_column(time, key.time_bin))
+            .or_default();
+        if column.len() <= row {
+            column.resize(row + 1, 0);
+            self.rows = self.rows.max(column.len());
+        }
+        column[row] += 1;
+    }
+
+    /// Number of replies in the bin under a point on the plot.
+    pub fn count_at(&self, position: PlotPoint) -> Option<u32> {
+        let key = self.key?;
+        let column = (position.x / f64::from(key.time_bin)).floor() as i64;
+        let row = usize::try_from((position.y / f64::from(key.height_bin)).floor() as i64).ok()?;
+        Some(
+            self.columns
+                .get(&column)
+                .and_then(|rows| rows.get(row).copied())
+                .unwrap_or(0),
+        )
+    }
+
+    pub fn draw(&mut self, plot_ui: &mut PlotUi, bounds: view::Bounds, params: &Params) {
+        let key = match self.key {
+            Some(key) => key,
+            None => return,
+        };
+        let frame = match self.frame(key, bounds, params.log_scale) {
+            Some(frame) => frame,
+            None => return,
+        };
+        let time_bin = f64::from(key.time_bin);
+        let height_bin = f64::from(key.height_bin);
+        let (first_column, last_column) = frame.columns;
+        let (first_row, last_row) = frame.rows;
+
+        // Neighbouring bins are merged when there are too many to draw
+        let column_stride = ((last_column - first_column) / MAX_BINS) + 1;
+        let row_stride = ((last_row - first_row) / MAX_BINS) + 1;
+        let width = ((last_column - first_column) / column_stride + 1) as usize;
+        let height = ((last_row - first_row) / row_stride + 1) as usize;
+
+        if self.drawn != Some(frame) || self.texture.is_none() {
+            let mut counts = vec![0u32; width * height];
+            for (column, rows) in self.columns.range(first_column..=last_column) {
+                let x = ((column - first_column) / column_stride) as usize;
+                for (row, count) in rows.iter().enumerate() {
+                    let row = row as i64;
+                    if row < first_row || row > last_row {
+                        continue;
+                    }
+
+                    // Images start at the top, which is the highest bin
+                    let y = height - 1 - ((row - first_row) / row_stride) as usize;
+                    counts[y * width + x] += count;
+                }
+            }
+
+            let max = counts.iter().copied().max().unwrap_or(0);
+            let pixels = counts
+                .iter()
+                .map(|count| color(*count, max, params.log_scale))
+                .collect();
+            let image = ColorImage {
+                size: [width, height],
+                pixels,
+            };
+
+            match &mut self.texture {
+                Some(texture) => texture.set(image, TextureOptions::NEAREST),
+                None => {
+                    self.texture = Some(plot_ui.ctx().load_texture(
+                        "Heatmap",
+                        image,
+                        TextureOptions::NEAREST,
+                    ));
+                }
+            }
+            self.drawn = Some(frame);
+        }
+
+        if let Some(texture) = &self.texture {
+            let left = first_column as f64 * time_bin;
+            let right = (first_column + width as i64 * column_stride) as f64 * time_bin;
+            let bottom = first_row as f64 * height_bin;
+            let top = (first_row + height as i64 * row_stride) as f64 * height_bin;
+
+            plot_ui.image(PlotImage::new(
+                texture,
+                PlotPoint::new((left + right) / 2.0, (bottom + top) / 2.0),
+                [(right - left) as f32, (top - bottom) as f32],
+            ));
+        }
+    }
+
+    // The visible bins, or `None` when none of them hold data. Only the bins that do are drawn,
+    // which also keeps the image size in range however far out the view is zoomed.
+    fn frame(&self, key: Key, bounds: view::Bounds, log_scale: bool) -> Option<Frame> {
+        let time_bin = f64::from(key.time_bin);
+        let height_bin = f64::from(key.height_bin);
+
+        let data_columns = match (self.columns.keys().next(), self.columns.keys().next_back()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return None,
+        };
+        let data_rows = (0, self.rows as i64 - 1);
+        let visible = |min: f64, max: f64, bin: f64, (first, last): (i64, i64)| {
+            let (min, max) = ((min / bin).floor() as i64, (max / bin).floor() as i64);
+            if max < first || min > last {
+                None
+            } else {
+                Some((min.max(first), max.min(last)))
+            }
+        };
+        match (
+            visible(bounds.min_x, bounds.max_x, time_bin, data_columns),
+            visible(bounds.min_y, bounds.max_y, height_bin, data_rows),
+        ) {
+            (Some(columns), Some(rows)) => Some(Frame {
+                columns,
+                rows,
+                log_scale,
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn color(count: u32, max: u32, log_scale: bool) -> Color32 {
+    if count == 0 || max == 0 {
+        return Color32::TRANSPARENT;
+    }
+
+    let scale = if log_scale {
+        f64::from(count).ln_1p() / f64::from(max).ln_1p()
+    } else {
+        f64::from(count) / f64::from(max)
+    };
+
+    let position = scale * (COLOR_MAP.len() - 1) as f64;
+    let index = (position.floor() as usize).min(COLOR_MAP.len() - 2);
+    let t = (position - index as f64) as f32;
+    let (from, to) = (COLOR_MAP[index], COLOR_MAP[index + 1]);
+    let lerp = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * t).round() as u8;
+
+    Color32::from_rgb(
+        lerp(from.r(), to.r()),
+        lerp(from.g(), to.g()),
+        lerp(from.b(), to.b()),
+    )
+}
+
+fn time_column(time: SystemTime, time_bin: u32) -> i64 {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    i64::try_from(seconds / u64::from(time_bin)).unwrap_or(i64::MAX)
+}
+
+fn format_seconds(seconds: u32) -> String {
+    if seconds >= 3600 {
+        format!("{:} h", seconds / 3600)
+    } else if seconds >= 60 {
+        format!("{:} min", seconds / 60)
+    } else {
+        format!("{:} s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::point;
+
+    fn heatmap() -> Heatmap {
+        let mut store = store::Store::default();
+        store.extend((0..600).map(|second| point(f64::from(second), 30_000 + second * 10)));
+        let mut heatmap = Heatmap::default();
+        heatmap.update(&store, None, 0, &Params::default());
+        heatmap
+    }
+
+    #[test]
+    fn frame_stays_within_the_data() {
+        let heatmap = heatmap();
+        let key = heatmap.key.unwrap();
+        let data_columns = (
+            *heatmap.columns.keys().next().unwrap(),
+            *heatmap.columns.keys().next_back().unwrap(),
+        );
+        let data_rows = (0, heatmap.rows as i64 - 1);
+
+        for (min, max) in [
+            (-1e300, 1e300),
+            (f64::MIN, f64::MAX),
+            (f64::NAN, f64::INFINITY),
+        ] {
+            let bounds = view::Bounds {
+                min_x: min,
+                max_x: max,
+                min_y: min,
+                max_y: max,
+            };
+            let frame = heatmap.frame(key, bounds, true).unwrap();
+            assert_eq!(frame.columns, data_columns);
+            assert_eq!(frame.rows, data_rows);
+        }
+    }
+
+    #[test]
+    fn frame_is_empty_away_from_the_data() {
+        let heatmap = heatmap();
+        let key = heatmap.key.unwrap();
+        let before = view::Bounds {
+            min_x: 0.0,
+            max_x: 1000.0,
+            min_y: 0.0,
+            max_y: 70_000.0,
+        };
+        assert!(heatmap.frame(key, before, true).is_none());
+
+        let above = view::Bounds {
+            min_x: f64::MIN,
+            max_x: f64::MAX,
+            min_y: 100_000.0,
+            max_y: 200_000.0,
+        };
+        assert!(heatmap.frame(key, above, true).is_none());
+    }
+}
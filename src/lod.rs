@@ -0,0 +1,241 @@
+use crate::{data, store, view};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// Which layer of the plot a point is drawn on.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Layer {
+    Kept,
+    Rejected,
+}
+
+// Everything that, when changed, means the points need binning again from scratch
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Key {
+    cell_size: [f64; 2],
+    store_generation: u64,
+    filter_generation: u64,
+}
+
+/// Reduces the points to at most one per layer and aircraft for each pixel sized cell, so the
+/// plot draws a bounded number of points that look the same as drawing all of them.
+///
+/// Cells are aligned to the time since the unix epoch rather than the view, so panning or
+/// following live data keeps them valid and only new points need binning each frame.
+#[derive(Default)]
+pub struct Decimator {
+    key: Option<Key>,
+    // The newest point in each cell, ordered by time so the visible cells are a range. Aircraft
+    // are binned apart so each keeps its own colour.
+    cells: BTreeMap<(i64, i64, Layer, Option<u32>), data::Point>,
+    // Time of the newest point binned so far
+    binned_until: Option<SystemTime>,
+}
+
+impl Decimator {
+    /// Bins any new points. `keep` is the denoise filter, points past its end are not binned
+    /// until they have been classified.
+    pub fn update(
+        &mut self,
+        store: &store::Store,
+        keep: Option<&[bool]>,
+        filter_generation: u64,
+        per_point: [f64; 2],
+    ) {
+        let key = Key {
+            cell_size: [cell_size(per_point[0]), cell_size(per_point[1])],
+            store_generation: store.generation(),
+            filter_generation,
+        };
+        if self.key != Some(key) {
+            self.key = Some(key);
+            self.cells.clear();
+            self.binned_until = None;
+        }
+
+        let points = store.as_slice();
+
+        // Drop cells whose newest point has been pruned, every older point in them is gone too
+        if let Some(oldest) = points.first() {
+            let oldest_cell = cell_index(view::x_from_time(oldest.time), key.cell_size[0]);
+            let stale: Vec<_> = self
+                .cells
+                .iter()
+                .take_while(|((x, _, _, _), _)| *x <= oldest_cell)
+                .filter(|(_, point)| point.time < oldest.time)
+                .map(|(cell, _)| *cell)
+                .collect();
+            for cell in stale {
+                self.cells.remove(&cell);
+            }
+        }
+
+        let from = self.binned_until.map_or(0, |until| {
+            points.partition_point(|point| point.time <= until)
+        });
+        let to = keep.map_or(points.len(), |keep| keep.len().min(points.len()));
+
+        for index in from..to {
+            let point = &points[index];
+            let layer = match keep {
+                Some(keep) if !keep[index] => Layer::Rejected,
+                _ => Layer::Kept,
+            };
+            let cell = (
+                cell_index(view::x_from_time(point.time), key.cell_size[0]),
+                cell_index(f64::from(point.height), key.cell_size[1]),
+                layer,
+                point.message.icao(),
+            );
+            self.cells.insert(cell, point.clone());
+            self.binned_until = Some(point.time);
+        }
+    }
+
+    /// The points to draw between the given bounds.
+    pub fn visible(&self, bounds: view::Bounds) -> impl Iterator<Item = (Layer, &data::Point)> {
+        let cell_size = self.key.map_or(1.0, |key| key.cell_size[0]);
+        let from = (
+            cell_index(bounds.min_x, cell_size),
+            i64::MIN,
+            Layer::Kept,
+            None,
+        );
+        let to = (
+            cell_index(bounds.max_x, cell_size),
+            i64::MAX,
+            Layer::Rejected,
+            Some(u32::MAX),
+        );
+
+        self.cells
+            .range(from..=to)
+            .map(|((_, _, layer, _), point)| (*layer, point))
+            .filter(move |(_, point)| {
+                let x = view::x_from_time(point.time);
+                x >= bounds.min_x && x <= bounds.max_x && bounds.contains_y(f64::from(point.height))
+            })
+    }
+}
+
+// Rounded down to a power of two, so small changes to the view size or zoom don't start the
+// binning again. Cells end up between half a pixel and a pixel across.
+fn cell_size(per_point: f64) -> f64 {
+    if per_point > 0.0 && per_point.is_finite() {
+        per_point.log2().floor().exp2()
+    } else {
+        1.0
+    }
+}
+
+fn cell_index(value: f64, cell_size: f64) -> i64 {
+    (value / cell_size).floor() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::point;
+
+    const PER_POINT: [f64; 2] = [1.0, 128.0];
+
+    fn everything() -> view::Bounds {
+        view::Bounds {
+            min_x: 0.0,
+            max_x: f64::MAX,
+            min_y: 0.0,
+            max_y: f64::MAX,
+        }
+    }
+
+    // Heights in each second, from each second's column of the plot
+    fn columns<'a>(points: impl Iterator<Item = &'a data::Point>) -> BTreeMap<u64, Vec<u32>> {
+        let mut columns: BTreeMap<u64, Vec<u32>> = BTreeMap::new();
+        for point in points {
+            let second = view::x_from_time(point.time).floor() as u64;
+            columns.entry(second).or_default().push(point.height);
+        }
+        columns
+    }
+
+    // Several replies a second, many sharing a pixel
+    fn points(seconds: u32) -> Vec<data::Point> {
+        (0..seconds * 20)
+            .map(|index| {
+                let height = 30_000 + (index * 7919) % 2_000;
+                point(f64::from(index) / 20.0, height)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_extremes_of_each_column() {
+        let points = points(60);
+        let mut store = store::Store::default();
+        store.extend(points.clone());
+        let mut decimator = Decimator::default();
+        decimator.update(&store, None, 0, PER_POINT);
+
+        let drawn: Vec<_> = decimator.visible(everything()).collect();
+        assert!(drawn.len() < points.len());
+        assert!(drawn.iter().all(|(layer, _)| *layer == Layer::Kept));
+
+        let all = columns(points.iter());
+        let decimated = columns(drawn.iter().map(|(_, point)| *point));
+        assert_eq!(all.len(), decimated.len());
+        for (second, heights) in &all {
+            let kept = &decimated[second];
+            let cell = |height: u32| cell_index(f64::from(height), PER_POINT[1]);
+            let extremes = |heights: &[u32]| {
+                let min = heights.iter().copied().min().unwrap();
+                let max = heights.iter().copied().max().unwrap();
+                (cell(min), cell(max))
+            };
+            assert_eq!(extremes(heights), extremes(kept), "{second}");
+        }
+    }
+
+    #[test]
+    fn starts_again_after_extend() {
+        let points = points(60);
+        let mut store = store::Store::default();
+        store.extend(points[600..].to_vec());
+        let mut decimator = Decimator::default();
+        decimator.update(&store, None, 0, PER_POINT);
+        let before = decimator.visible(everything()).count();
+
+        // An older recording lands before everything binned so far
+        store.extend(points[..600].to_vec());
+        decimator.update(&store, None, 0, PER_POINT);
+
+        let mut fresh = Decimator::default();
+        fresh.update(&store, None, 0, PER_POINT);
+        let times = |decimator: &Decimator| {
+            decimator
+                .visible(everything())
+                .map(|(_, point)| point.time)
+                .collect::<Vec<_>>()
+        };
+        assert!(decimator.visible(everything()).count() > before);
+        assert_eq!(times(&decimator), times(&fresh));
+    }
+
+    #[test]
+    fn forgets_pruned_points() {
+        let points = points(60);
+        let mut store = store::Store::default();
+        for point in &points {
+            store.push(point.clone());
+        }
+        let mut decimator = Decimator::default();
+        decimator.update(&store, None, 0, PER_POINT);
+
+        let cutoff = points[600].time;
+        store.prune_before(cutoff);
+        decimator.update(&store, None, 0, PER_POINT);
+
+        let drawn: Vec<_> = decimator.visible(everything()).collect();
+        assert!(!drawn.is_empty());
+        assert!(drawn.iter().all(|(_, point)| point.time >= cutoff));
+    }
+}
@@ -2,16 +2,36 @@
 
 use std::sync::mpsc;
 use std::thread;
+use std::time::SystemTime;
 
 mod adsb;
+mod aircraft;
+mod alerts;
+mod annotations;
+mod autosave;
+mod correlate;
 mod data;
+mod database;
 mod denoise;
+mod emergency;
+mod gnss;
+mod heatmap;
+mod lod;
 mod plot;
+mod reference;
 mod settings;
+mod stats;
+mod store;
 mod ui;
+mod units;
+mod view;
+mod vrate;
 
 fn main() {
     let (plot_tx, plot_rx) = mpsc::channel::<data::Point>();
+    let (invalid_tx, invalid_rx) = mpsc::channel::<SystemTime>();
+    let (emergency_tx, emergency_rx) = mpsc::channel::<emergency::Reply>();
+    let (mode_s_tx, mode_s_rx) = mpsc::channel::<adsb::Report>();
     let (connect_tx, connect_rx) = mpsc::channel::<String>();
     let (connection_state_tx, connection_state_rx) = mpsc::channel::<adsb::ConnectionState>();
 
@@ -21,6 +41,9 @@ fn main() {
             adsb::run(adsb::Channels {
                 connect_rx,
                 plot_tx,
+                invalid_tx,
+                emergency_tx,
+                mode_s_tx,
                 connection_state_tx,
             });
         })
@@ -28,6 +51,9 @@ fn main() {
 
     ui::run(ui::Channels {
         plot_rx,
+        invalid_rx,
+        emergency_rx,
+        mode_s_rx,
         connection_state_rx,
         connect_tx,
     });
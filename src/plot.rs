@@ -1,58 +1,702 @@
-use crate::{data, settings};
+use crate::{
+    aircraft, annotations, correlate, data, database, gnss, heatmap, lod, reference, settings,
+    store, units, view, vrate,
+};
 use eframe::egui;
-use eframe::egui::plot::{MarkerShape, Plot, PlotPoints, Points};
-use eframe::egui::Color32;
+use eframe::egui::plot::{
+    Legend, Line, LineStyle, MarkerShape, Plot, PlotPoint, PlotPoints, PlotUi, Points, Polygon,
+    Text,
+};
+use eframe::egui::{Align2, Color32};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::ops::RangeInclusive;
 use std::time::SystemTime;
 use thousands::Separable;
 
+const PREVIEW_COLOR: Color32 = Color32::from_rgb(220, 80, 80);
+const REJECTED_COLOR: Color32 = Color32::from_rgba_premultiplied(60, 25, 25, 60);
+const TRACK_COLOR: Color32 = Color32::from_rgb(230, 180, 60);
+const REGION_COLOR: Color32 = Color32::from_rgb(100, 170, 230);
+const INSPECTED_COLOR: Color32 = Color32::WHITE;
+// How close the pointer needs to be to a point to inspect it
+const HOVER_DISTANCE: f32 = 8.0;
+
+/// How the replies are drawn on the main plot.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Points,
+    Heatmap,
+}
+
+/// Which points passed the denoise filter and how the rejected ones are drawn. The filter can be
+/// shorter than the points while live data is pending. Aircraft are named from what the
+/// correlator and database know about them, and Mode C traces matched to them are labelled when
+/// `label_aircraft` is set.
+pub struct Layers<'a> {
+    pub keep: Option<&'a [bool]>,
+    // The denoise filter's generation, so cached work can tell when it starts again
+    pub generation: u64,
+    pub show_rejected: bool,
+    pub preview: bool,
+    pub correlator: &'a correlate::Correlator,
+    pub database: &'a database::Database,
+    pub gnss: &'a gnss::Comparison,
+    pub label_aircraft: bool,
+}
+
+#[derive(Default)]
+pub struct PlotState {
+    pub vertical_rate: vrate::Selection,
+    pub view: view::View,
+    pub decimator: lod::Decimator,
+    pub heatmap: heatmap::Heatmap,
+    // Point clicked on to keep its details open
+    pub inspected: Option<data::Point>,
+    pub annotations: annotations::Annotations,
+    pub aircraft: aircraft::Selection,
+}
+
 pub fn plot(
     ui: &mut egui::Ui,
-    points: &[data::Point],
+    store: &store::Store,
     settings: &settings::Settings,
     data_x_age: &SystemTime,
+    layers: Layers,
+    state: &mut PlotState,
 ) {
-    let y_fmt = |y, _range: &RangeInclusive<f64>| {
-        let ft: f64 = y;
-        format!("{:}ft", ft.separate_with_commas())
-    };
+    let converter = settings.altitude.converter();
+    let y_fmt = move |y, _range: &RangeInclusive<f64>| converter.format(y);
+
+    // The plots below the main one share the bottom of the window between them
+    let lower_plots = usize::from(settings.show_vertical_rate) + usize::from(settings.show_gnss);
+    let lower_height = ui.available_height() * 0.3;
+    let height = ui.available_height() - lower_height * lower_plots as f32;
 
-    Plot::new("Main plot")
-        .include_y(settings.min_display_height)
-        .include_y(settings.max_display_height)
-        .include_x(-f64::from(settings.max_display_age))
-        .include_x(0)
+    let points = store.as_slice();
+    let bounds = state.view.bounds(settings, *data_x_age);
+    let utc = settings.utc_time;
+
+    let response = Plot::new("Main plot")
+        .height(height)
+        .include_x(bounds.min_x)
+        .include_x(bounds.max_x)
+        .include_y(bounds.min_y)
+        .include_y(bounds.max_y)
+        .x_axis_formatter(move |x, _range| view::format_time(x, utc))
+        .x_grid_spacer(view::time_grid_spacer)
         .y_axis_formatter(y_fmt)
+        .y_grid_spacer(converter.grid_spacer())
         .show_x(false)
         .show_y(false)
-        .show_axes([false, settings.show_axis])
+        .show_axes([true, settings.show_axis])
         .allow_drag(false)
         .allow_scroll(false)
         .allow_zoom(false)
+        .allow_boxed_zoom(false)
+        .allow_double_click_reset(false)
+        .legend(Legend::default())
         .show(ui, |plot_ui| {
-            let mut series: Vec<[f64; 2]> = vec![];
-            for point in points.iter().rev() {
-                let millis_ago = match data_x_age.duration_since(point.time) {
-                    Ok(n) => n.as_millis(),
-                    Err(_) => continue, // Scrolled out out of view
+            let navigation = view::Navigation::new(plot_ui);
+            reference::draw(
+                plot_ui,
+                &settings.reference_lines,
+                &settings.altitude_bands,
+                bounds,
+                converter,
+            );
+            state.annotations.draw(plot_ui, bounds);
+
+            let (hovered, bin) = match settings.plot_mode {
+                Mode::Points => {
+                    let hovered = draw_points(
+                        plot_ui,
+                        &mut state.decimator,
+                        &mut state.aircraft,
+                        store,
+                        bounds,
+                        &layers,
+                        navigation.per_point(),
+                    );
+                    (hovered, None)
+                }
+                Mode::Heatmap => {
+                    state
+                        .heatmap
+                        .update(store, layers.keep, layers.generation, &settings.heatmap);
+                    state.heatmap.draw(plot_ui, bounds, &settings.heatmap);
+                    let bin = plot_ui
+                        .pointer_coordinate()
+                        .and_then(|pointer| state.heatmap.count_at(pointer));
+                    (None, bin)
+                }
+            };
+
+            select_vertical_rate(plot_ui, points, &mut state.vertical_rate);
+
+            // Alt + click leaves a note rather than picking a point
+            let alt = plot_ui.ctx().input().modifiers.alt;
+            if plot_ui.plot_clicked() {
+                match (alt, plot_ui.pointer_coordinate()) {
+                    (true, Some(pointer)) => state.annotations.add(
+                        view::time_from_x(pointer.x),
+                        Some(pointer.y.max(0.0) as u32),
+                        "Note".to_owned(),
+                    ),
+                    (true, None) => {}
+                    (false, _) => {
+                        if let Some(point) = hovered {
+                            state.inspected = Some(point.clone());
+                        }
+                    }
+                }
+            }
+            if plot_ui.plot_secondary_clicked() {
+                state.inspected = None;
+            }
+
+            draw_vertical_rate(plot_ui, &state.vertical_rate);
+
+            if layers.label_aircraft {
+                draw_aircraft(plot_ui, &layers, &state.aircraft, bounds);
+            }
+
+            if let Some(point) = &state.inspected {
+                plot_ui.points(
+                    Points::new(PlotPoints::new(vec![[
+                        view::x_from_time(point.time),
+                        f64::from(point.height),
+                    ]]))
+                    .radius(4.0)
+                    .shape(MarkerShape::Circle)
+                    .filled(false)
+                    .color(INSPECTED_COLOR),
+                );
+            }
+
+            if let Some((start, end)) = state.view.zoom_box(&navigation) {
+                plot_ui.polygon(
+                    Polygon::new(PlotPoints::new(vec![
+                        [start.x, start.y],
+                        [end.x, start.y],
+                        [end.x, end.y],
+                        [start.x, end.y],
+                    ]))
+                    .color(Color32::WHITE)
+                    .fill_alpha(0.05),
+                );
+            }
+
+            (hovered.cloned(), bin, navigation)
+        });
+
+    let (hovered, bin, navigation) = response.inner;
+    state.view.interact(bounds, &response.response, &navigation);
+
+    if let Some(point) = hovered {
+        response.response.on_hover_ui_at_pointer(|ui| {
+            point_details(ui, points, &point, &layers, utc, converter);
+        });
+    } else if let Some(count) = bin {
+        response.response.on_hover_ui_at_pointer(|ui| {
+            ui.label(format!("{:} replies", count.separate_with_commas()));
+        });
+    }
+
+    if let Some(point) = state.inspected.clone() {
+        let mut open = true;
+        egui::Window::new("Reply")
+            .open(&mut open)
+            .resizable(false)
+            .show(ui.ctx(), |ui| {
+                point_details(ui, points, &point, &layers, utc, converter);
+                if ui
+                    .button("Follow trace")
+                    .on_hover_text("Estimate the vertical rate along the trace through this reply")
+                    .clicked()
+                {
+                    state.vertical_rate.tracks.push(vrate::follow(
+                        points,
+                        point.time,
+                        f64::from(point.height),
+                    ));
+                }
+            });
+
+        if !open {
+            state.inspected = None;
+        }
+    }
+
+    if settings.show_vertical_rate {
+        vertical_rate_plot(ui, settings, bounds, &state.vertical_rate, lower_height);
+    }
+    if settings.show_gnss {
+        gnss_plot(ui, settings, bounds, &layers, &state.aircraft, lower_height);
+    }
+}
+
+// Marks where each matched trace was last seen, in the colour of the aircraft it matched
+fn draw_aircraft(
+    plot_ui: &mut PlotUi,
+    layers: &Layers,
+    selection: &aircraft::Selection,
+    bounds: view::Bounds,
+) {
+    for found in layers.correlator.matches() {
+        let position = PlotPoint::new(view::x_from_time(found.time), f64::from(found.height));
+        if position.x < bounds.min_x
+            || position.x > bounds.max_x
+            || !bounds.contains_y(position.y)
+            || selection.display(Some(found.icao)) == aircraft::Display::Hidden
+        {
+            continue;
+        }
+
+        let color = selection.color(Some(found.icao));
+        plot_ui.points(
+            Points::new(PlotPoints::new(vec![[position.x, position.y]]))
+                .radius(3.0)
+                .shape(MarkerShape::Diamond)
+                .color(color)
+                .name(aircraft::name(
+                    Some(found.icao),
+                    layers.correlator,
+                    layers.database,
+                )),
+        );
+        plot_ui.text(
+            Text::new(position, found.label(layers.database.get(found.icao)))
+                .color(color)
+                .anchor(Align2::LEFT_BOTTOM),
+        );
+    }
+}
+
+// Draws the decimated points with a series for each aircraft, returning the one under the
+// pointer.
+fn draw_points<'a>(
+    plot_ui: &mut PlotUi,
+    decimator: &'a mut lod::Decimator,
+    selection: &mut aircraft::Selection,
+    store: &store::Store,
+    bounds: view::Bounds,
+    layers: &Layers,
+    per_point: [f64; 2],
+) -> Option<&'a data::Point> {
+    decimator.update(store, layers.keep, layers.generation, per_point);
+
+    let mut in_view = BTreeSet::new();
+    let mut visible: Vec<&data::Point> = vec![];
+    let mut positions: Vec<[f64; 2]> = vec![];
+    let mut series: BTreeMap<Option<u32>, Vec<[f64; 2]>> = BTreeMap::new();
+    let mut removed: Vec<[f64; 2]> = vec![];
+    for (layer, point) in decimator.visible(bounds) {
+        let aircraft = point.message.icao();
+        in_view.insert(aircraft);
+        if selection.display(aircraft) == aircraft::Display::Hidden {
+            continue;
+        }
+
+        let position = [view::x_from_time(point.time), f64::from(point.height)];
+        match layer {
+            lod::Layer::Kept => {
+                visible.push(point);
+                positions.push(position);
+                series.entry(aircraft).or_default().push(position);
+            }
+            lod::Layer::Rejected if layers.preview || layers.show_rejected => {
+                removed.push(position);
+            }
+            lod::Layer::Rejected => {}
+        }
+    }
+
+    let hovered = nearest_point(plot_ui, &visible, &positions);
+
+    for (aircraft, series) in series {
+        let radius = match selection.display(aircraft) {
+            aircraft::Display::Highlighted => 2.0,
+            _ => 1.0,
+        };
+        let points = Points::new(PlotPoints::new(series))
+            .radius(radius)
+            .shape(MarkerShape::Circle)
+            .color(selection.color(aircraft))
+            .name(aircraft::name(aircraft, layers.correlator, layers.database));
+        plot_ui.points(points);
+    }
+    selection.set_in_view(in_view);
+
+    if !removed.is_empty() {
+        let removed = Points::new(PlotPoints::new(removed))
+            .radius(1.0)
+            .shape(MarkerShape::Circle)
+            .color(if layers.preview {
+                PREVIEW_COLOR
+            } else {
+                REJECTED_COLOR
+            });
+        plot_ui.points(removed);
+    }
+
+    hovered
+}
+
+fn point_details(
+    ui: &mut egui::Ui,
+    points: &[data::Point],
+    point: &data::Point,
+    layers: &Layers,
+    utc: bool,
+    converter: units::Converter,
+) {
+    egui::Grid::new("Point details").show(ui, |ui| {
+        ui.label("Time");
+        ui.label(view::format_timestamp(point.time, utc));
+        ui.end_row();
+
+        ui.label("Altitude");
+        ui.label(converter.format(f64::from(point.height)));
+        ui.end_row();
+
+        ui.label("Message");
+        match point.message {
+            data::Message::Unknown => ui.label("Unknown"),
+            data::Message::ModeAc { code } => ui.label(format!("Mode A/C, raw code {code:04X}")),
+            data::Message::ModeS { .. } => ui.label("Mode S"),
+        };
+        ui.end_row();
+
+        ui.label("Receiver");
+        ui.label(point.source.as_deref().unwrap_or("Unknown"));
+        ui.end_row();
+
+        // Mode S points come from a known aircraft, Mode C ones only when they follow one
+        let icao = match point.message.icao() {
+            Some(icao) => {
+                ui.label("Aircraft");
+                ui.label(aircraft::label(
+                    icao,
+                    layers.correlator.callsign(icao),
+                    None,
+                ));
+                ui.end_row();
+                Some(icao)
+            }
+            None if layers.label_aircraft => {
+                let found = layers.correlator.find(point);
+                ui.label("Aircraft");
+                match found {
+                    Some(found) => ui.label(format!(
+                        "{:} ({:} of {:} altitudes match)",
+                        found.label(None),
+                        found.matched,
+                        found.samples
+                    )),
+                    None => ui.label("Unknown"),
                 };
-                let seconds_ago = u32::try_from(millis_ago / 1000).unwrap();
+                ui.end_row();
+                found.map(|found| found.icao)
+            }
+            None => None,
+        };
 
-                if seconds_ago > settings.max_display_age {
-                    break;
+        if let Some(entry) = icao.and_then(|icao| layers.database.get(icao)) {
+            for (name, value) in [
+                ("Registration", &entry.registration),
+                ("Type", &entry.type_code),
+                ("Operator", &entry.operator),
+            ] {
+                if !value.is_empty() {
+                    ui.label(name);
+                    ui.label(value);
+                    ui.end_row();
                 }
+            }
+        }
+
+        ui.label("Vertical rate");
+        match vrate::estimate_at(points, point.time, f64::from(point.height)) {
+            Some(estimate) => ui.label(format!(
+                "{:} from {:} replies",
+                vrate::format_rate(estimate.feet_per_minute),
+                estimate.samples
+            )),
+            None => ui.label("Unknown"),
+        };
+        ui.end_row();
+    });
+}
+
+// Shift + drag selects a region and right clicking clears. Following a trace is started from the
+// inspector, as it's too slow to do on every click.
+fn select_vertical_rate(
+    plot_ui: &mut PlotUi,
+    points: &[data::Point],
+    selection: &mut vrate::Selection,
+) {
+    let (shift, pressed, released) = {
+        let input = plot_ui.ctx().input();
+        (
+            input.modifiers.shift,
+            input.pointer.any_pressed() && input.pointer.primary_down(),
+            input.pointer.primary_released(),
+        )
+    };
+    let pointer = plot_ui
+        .pointer_coordinate()
+        .map(|pointer| (view::time_from_x(pointer.x), pointer.y));
+
+    if shift && pressed {
+        selection.region_drag_start = pointer;
+    }
+
+    // Preview the region while it is being dragged out
+    selection.preview = selection
+        .region_drag_start
+        .zip(pointer)
+        .and_then(|(start, end)| vrate::Region::between(start, end))
+        .map(|region| vrate::RegionRate::new(region, points));
+
+    if released && selection.region_drag_start.take().is_some() {
+        if let Some(preview) = selection.preview.take() {
+            selection.region = Some(preview);
+        }
+    }
+
+    if plot_ui.plot_secondary_clicked() {
+        selection.clear();
+    }
+}
 
-                if point.height > settings.max_display_height {
-                    continue;
+fn draw_vertical_rate(plot_ui: &mut PlotUi, selection: &vrate::Selection) {
+    for track in &selection.tracks {
+        let series: Vec<[f64; 2]> = track
+            .iter()
+            .map(|estimate| [view::x_from_time(estimate.time), estimate.height])
+            .collect();
+        plot_ui.line(Line::new(PlotPoints::new(series)).color(TRACK_COLOR));
+
+        if let Some(last) = track.last() {
+            plot_ui.text(
+                Text::new(
+                    PlotPoint::new(view::x_from_time(last.time), last.height),
+                    vrate::format_rate(last.feet_per_minute),
+                )
+                .color(TRACK_COLOR)
+                .anchor(Align2::LEFT_BOTTOM),
+            );
+        }
+    }
+
+    for vrate::RegionRate { region, estimate } in selection.regions() {
+        let left = view::x_from_time(region.start);
+        let right = view::x_from_time(region.end);
+        plot_ui.polygon(
+            Polygon::new(PlotPoints::new(vec![
+                [left, region.min_height],
+                [right, region.min_height],
+                [right, region.max_height],
+                [left, region.max_height],
+            ]))
+            .color(REGION_COLOR)
+            .fill_alpha(0.1),
+        );
+
+        let label = match estimate {
+            Some(estimate) => {
+                let duration = vrate::seconds_between(region.start, region.end);
+                let end_height = estimate.height + estimate.feet_per_minute / 60.0 * duration;
+                plot_ui.line(
+                    Line::new(PlotPoints::new(vec![
+                        [left, estimate.height],
+                        [right, end_height],
+                    ]))
+                    .color(REGION_COLOR)
+                    .width(2.0),
+                );
+                vrate::format_rate(estimate.feet_per_minute)
+            }
+            None => "Not enough points".to_owned(),
+        };
+        plot_ui.text(
+            Text::new(PlotPoint::new(left, region.max_height), label)
+                .color(REGION_COLOR)
+                .anchor(Align2::LEFT_BOTTOM),
+        );
+    }
+}
+
+fn vertical_rate_plot(
+    ui: &mut egui::Ui,
+    settings: &settings::Settings,
+    bounds: view::Bounds,
+    selection: &vrate::Selection,
+    height: f32,
+) {
+    let y_fmt = |y, _range: &RangeInclusive<f64>| vrate::format_rate(y);
+    let utc = settings.utc_time;
+
+    Plot::new("Vertical rate plot")
+        .height(height)
+        .include_y(-3000)
+        .include_y(3000)
+        .include_x(bounds.min_x)
+        .include_x(bounds.max_x)
+        .x_axis_formatter(move |x, _range| view::format_time(x, utc))
+        .x_grid_spacer(view::time_grid_spacer)
+        .y_axis_formatter(y_fmt)
+        .show_x(false)
+        .show_axes([true, settings.show_axis])
+        .allow_drag(false)
+        .allow_scroll(false)
+        .allow_zoom(false)
+        .allow_boxed_zoom(false)
+        .show(ui, |plot_ui| {
+            for track in &selection.tracks {
+                let series: Vec<[f64; 2]> = track
+                    .iter()
+                    .map(|estimate| [view::x_from_time(estimate.time), estimate.feet_per_minute])
+                    .collect();
+                plot_ui.line(Line::new(PlotPoints::new(series)).color(TRACK_COLOR));
+            }
+
+            for vrate::RegionRate { region, estimate } in selection.regions() {
+                if let Some(estimate) = estimate {
+                    plot_ui.line(
+                        Line::new(PlotPoints::new(vec![
+                            [view::x_from_time(region.start), estimate.feet_per_minute],
+                            [view::x_from_time(region.end), estimate.feet_per_minute],
+                        ]))
+                        .color(REGION_COLOR)
+                        .width(2.0),
+                    );
                 }
+            }
+        });
+}
 
-                series.push([-(millis_ago as f64 / 1000.0), f64::from(point.height)]);
+// Both altitudes of each aircraft reporting them, barometric solid and GNSS dashed, above the
+// difference between them
+fn gnss_plot(
+    ui: &mut egui::Ui,
+    settings: &settings::Settings,
+    bounds: view::Bounds,
+    layers: &Layers,
+    selection: &aircraft::Selection,
+    height: f32,
+) {
+    let utc = settings.utc_time;
+    let start = view::time_from_x(bounds.min_x);
+    let end = view::time_from_x(bounds.max_x);
+    let tracks: Vec<_> = layers
+        .gnss
+        .tracks()
+        .filter(|(icao, _)| selection.display(Some(*icao)) != aircraft::Display::Hidden)
+        .map(|(icao, track)| {
+            (
+                aircraft::name(Some(icao), layers.correlator, layers.database),
+                selection.color(Some(icao)),
+                time_series(&track.barometric, start, end),
+                time_series(&track.gnss, start, end),
+                time_series(&track.difference, start, end),
+            )
+        })
+        .collect();
+
+    let converter = settings.altitude.converter();
+    let y_fmt = move |y: f64, _range: &RangeInclusive<f64>| converter.format_height(y);
+    Plot::new("GNSS altitude plot")
+        .height(height / 2.0)
+        .include_x(bounds.min_x)
+        .include_x(bounds.max_x)
+        .x_axis_formatter(move |x, _range| view::format_time(x, utc))
+        .x_grid_spacer(view::time_grid_spacer)
+        .y_axis_formatter(y_fmt)
+        .show_axes([true, settings.show_axis])
+        .allow_drag(false)
+        .allow_scroll(false)
+        .allow_zoom(false)
+        .allow_boxed_zoom(false)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            for (name, color, barometric, gnss, _) in &tracks {
+                plot_ui.line(
+                    Line::new(PlotPoints::new(barometric.clone()))
+                        .color(*color)
+                        .name(name),
+                );
+                plot_ui.line(
+                    Line::new(PlotPoints::new(gnss.clone()))
+                        .color(*color)
+                        .style(LineStyle::dashed_loose())
+                        .name(name),
+                );
             }
+        });
 
-            let points = Points::new(PlotPoints::new(series))
-                .radius(1.0)
-                .shape(MarkerShape::Circle)
-                .color(Color32::from_rgb(100, 200, 100));
-            plot_ui.points(points);
+    let difference_fmt = move |y: f64, _range: &RangeInclusive<f64>| {
+        let height = converter.format_height(y);
+        if y.round() > 0.0 {
+            format!("+{height}")
+        } else {
+            height
+        }
+    };
+    Plot::new("GNSS difference plot")
+        .height(height / 2.0)
+        .include_x(bounds.min_x)
+        .include_x(bounds.max_x)
+        .include_y(0.0)
+        .x_axis_formatter(move |x, _range| view::format_time(x, utc))
+        .x_grid_spacer(view::time_grid_spacer)
+        .y_axis_formatter(difference_fmt)
+        .show_axes([true, settings.show_axis])
+        .allow_drag(false)
+        .allow_scroll(false)
+        .allow_zoom(false)
+        .allow_boxed_zoom(false)
+        .show(ui, |plot_ui| {
+            for (name, color, _, _, difference) in tracks {
+                plot_ui.line(
+                    Line::new(PlotPoints::new(difference))
+                        .color(color)
+                        .name(name),
+                );
+            }
         });
 }
+
+fn time_series<T: Copy + Into<f64>>(
+    values: &VecDeque<(SystemTime, T)>,
+    start: SystemTime,
+    end: SystemTime,
+) -> Vec<[f64; 2]> {
+    let from = values.partition_point(|(time, _)| *time < start);
+    let to = values.partition_point(|(time, _)| *time <= end);
+    values
+        .range(from..to)
+        .map(|(time, value)| [view::x_from_time(*time), (*value).into()])
+        .collect()
+}
+
+fn nearest_point<'a>(
+    plot_ui: &PlotUi,
+    visible: &[&'a data::Point],
+    series: &[[f64; 2]],
+) -> Option<&'a data::Point> {
+    let pointer = plot_ui.screen_from_plot(plot_ui.pointer_coordinate()?);
+
+    visible
+        .iter()
+        .zip(series)
+        .map(|(point, [x, y])| {
+            let position = plot_ui.screen_from_plot(PlotPoint::new(*x, *y));
+            (*point, position.distance(pointer))
+        })
+        .filter(|(_, distance)| *distance <= HOVER_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(point, _)| point)
+}
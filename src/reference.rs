@@ -0,0 +1,172 @@
+use crate::{units, view};
+use eframe::egui;
+use eframe::egui::plot::{HLine, PlotPoint, PlotPoints, PlotUi, Polygon, Text};
+use eframe::egui::{Align2, Color32};
+use serde::{Deserialize, Serialize};
+
+/// A horizontal line across the plot at a fixed altitude.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Line {
+    pub label: String,
+    pub enabled: bool,
+    pub height: u32,
+    pub color: [u8; 3],
+}
+
+impl Default for Line {
+    fn default() -> Self {
+        Line {
+            label: "FL450".to_owned(),
+            enabled: true,
+            height: 45_000,
+            color: [200, 200, 200],
+        }
+    }
+}
+
+/// A shaded range of altitudes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Band {
+    pub label: String,
+    pub enabled: bool,
+    pub min_height: u32,
+    pub max_height: u32,
+    pub color: [u8; 3],
+}
+
+impl Default for Band {
+    fn default() -> Self {
+        Band {
+            label: "RVSM".to_owned(),
+            enabled: true,
+            min_height: 29_000,
+            max_height: 41_000,
+            color: [100, 170, 230],
+        }
+    }
+}
+
+pub fn default_lines() -> Vec<Line> {
+    vec![
+        Line::default(),
+        Line {
+            label: "FL600".to_owned(),
+            height: 60_000,
+            ..Line::default()
+        },
+    ]
+}
+
+fn height_ui(ui: &mut egui::Ui, height: &mut u32, converter: units::Converter) {
+    ui.add(
+        egui::DragValue::new(height)
+            .speed(100)
+            .clamp_range(0..=130_000)
+            .custom_formatter(|x, _| converter.format(x))
+            .custom_parser(|text| converter.parse(text)),
+    );
+}
+
+pub fn lines_ui(ui: &mut egui::Ui, lines: &mut Vec<Line>, converter: units::Converter) {
+    let mut remove = None;
+    for (index, line) in lines.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut line.enabled, "");
+            ui.color_edit_button_srgb(&mut line.color);
+            ui.add(egui::TextEdit::singleline(&mut line.label).desired_width(80.0));
+            height_ui(ui, &mut line.height, converter);
+            if ui.small_button("Delete").clicked() {
+                remove = Some(index);
+            }
+        });
+    }
+    if let Some(index) = remove {
+        lines.remove(index);
+    }
+
+    if ui.button("Add line").clicked() {
+        lines.push(Line::default());
+    }
+}
+
+pub fn bands_ui(ui: &mut egui::Ui, bands: &mut Vec<Band>, converter: units::Converter) {
+    let mut remove = None;
+    for (index, band) in bands.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut band.enabled, "");
+            ui.color_edit_button_srgb(&mut band.color);
+            ui.add(egui::TextEdit::singleline(&mut band.label).desired_width(80.0));
+            height_ui(ui, &mut band.min_height, converter);
+            ui.label("to");
+            height_ui(ui, &mut band.max_height, converter);
+            if ui.small_button("Delete").clicked() {
+                remove = Some(index);
+            }
+        });
+    }
+    if let Some(index) = remove {
+        bands.remove(index);
+    }
+
+    if ui.button("Add band").clicked() {
+        bands.push(Band::default());
+    }
+}
+
+/// Draws the bands and lines behind everything else, labelled at the left edge of the view. Only
+/// the parts inside the view are drawn, as anything outside would stretch the plot to fit it.
+pub fn draw(
+    plot_ui: &mut PlotUi,
+    lines: &[Line],
+    bands: &[Band],
+    bounds: view::Bounds,
+    converter: units::Converter,
+) {
+    for band in bands.iter().filter(|band| band.enabled) {
+        let [r, g, b] = band.color;
+        let color = Color32::from_rgb(r, g, b);
+        let bottom = f64::from(band.min_height.min(band.max_height)).max(bounds.min_y);
+        let top = f64::from(band.min_height.max(band.max_height)).min(bounds.max_y);
+        if bottom >= top {
+            continue;
+        }
+
+        plot_ui.polygon(
+            Polygon::new(PlotPoints::new(vec![
+                [bounds.min_x, bottom],
+                [bounds.max_x, bottom],
+                [bounds.max_x, top],
+                [bounds.min_x, top],
+            ]))
+            .color(color)
+            .fill_alpha(0.08)
+            .width(0.0),
+        );
+        plot_ui.text(
+            Text::new(PlotPoint::new(bounds.min_x, top), &band.label)
+                .color(color)
+                .anchor(Align2::LEFT_TOP),
+        );
+    }
+
+    for line in lines.iter().filter(|line| line.enabled) {
+        let [r, g, b] = line.color;
+        let color = Color32::from_rgb(r, g, b);
+        let height = f64::from(line.height);
+        if !bounds.contains_y(height) {
+            continue;
+        }
+
+        plot_ui.hline(HLine::new(height).color(color).width(1.0));
+        plot_ui.text(
+            Text::new(
+                PlotPoint::new(bounds.min_x, height),
+                format!("{:} ({:})", line.label, converter.format(height)),
+            )
+            .color(color)
+            .anchor(Align2::LEFT_BOTTOM),
+        );
+    }
+}
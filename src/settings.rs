@@ -1,32 +1,211 @@
+use crate::{alerts, denoise, heatmap, plot, reference, units};
 use eframe::egui;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
 
+const STORAGE_KEY: &str = "settings";
+// Profiles are kept apart from the rest, so they survive settings that can no longer be read
+const PROFILES_KEY: &str = "profiles";
+// Bump when a change to the settings needs more than the defaults for new fields, and migrate
+// from the older version in `Settings::migrate`
+const VERSION: u32 = 1;
+
+// Missing fields take their default value, so settings saved by older versions still load
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct Settings {
+    // The version the settings were saved by
+    pub version: u32,
     pub hostname: String,
     pub show_axis: bool,
     pub max_data_age: u32,
     pub max_display_age: u32,
     pub min_display_height: u32,
     pub max_display_height: u32,
+    pub show_vertical_rate: bool,
+    pub show_gnss: bool,
+    pub utc_time: bool,
+    pub label_aircraft: bool,
+    // A local CSV or JSON file of registrations and types, left empty to not use one
+    pub aircraft_database: String,
+    // Empty when not autosaving
+    pub autosave_file: String,
+    pub show_statistics: bool,
+    pub statistics_threshold: u32,
+    pub altitude: units::Altitude,
+    pub reference_lines: Vec<reference::Line>,
+    pub altitude_bands: Vec<reference::Band>,
+    pub plot_mode: plot::Mode,
+    pub heatmap: heatmap::Params,
+    pub denoise: denoise::Params,
+    pub alert_rules: Vec<alerts::Rule>,
+    // Empty when alerts aren't logged to a file
+    pub alert_log: String,
+    #[serde(skip)]
+    pub profiles: Vec<Profile>,
+
+    // Saved values that couldn't be read, kept to be written back under another key rather than
+    // lost when the settings are next saved
+    #[serde(skip)]
+    unreadable: Vec<(String, String)>,
+}
+
+/// Saved settings for a receiver, so it is quick to switch between them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub hostname: String,
+    pub denoise: denoise::Params,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Settings {
-            hostname: "192.168.2.48:30002".to_owned(),
+            version: VERSION,
+            hostname: "localhost:30002".to_owned(),
             show_axis: true,
             max_data_age: 60 * 60,
             max_display_age: 10 * 60,
             min_display_height: 0,
             max_display_height: 70_000,
+            show_vertical_rate: false,
+            show_gnss: false,
+            utc_time: false,
+            label_aircraft: true,
+            aircraft_database: String::new(),
+            autosave_file: String::new(),
+            show_statistics: false,
+            statistics_threshold: 50_000,
+            altitude: units::Altitude::default(),
+            reference_lines: reference::default_lines(),
+            altitude_bands: vec![],
+            plot_mode: plot::Mode::default(),
+            heatmap: heatmap::Params::default(),
+            denoise: denoise::Params::default(),
+            alert_rules: vec![],
+            alert_log: alerts::default_log_file(),
+            profiles: vec![],
+            unreadable: vec![],
         }
     }
 }
 
 impl Settings {
-    pub fn ui(&mut self, open: &mut bool, ctx: &egui::Context, update_time: u128) {
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        let storage = match storage {
+            Some(storage) => storage,
+            None => return Settings::default(),
+        };
+
+        let mut unreadable = vec![];
+        let mut settings = read::<Settings>(storage, STORAGE_KEY, &mut unreadable)
+            .map_or_else(Settings::default, Settings::migrate);
+        settings.profiles = read(storage, PROFILES_KEY, &mut unreadable).unwrap_or_default();
+        settings.unreadable = unreadable;
+        settings
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, STORAGE_KEY, self);
+        eframe::set_value(storage, PROFILES_KEY, &self.profiles);
+        for (key, value) in &self.unreadable {
+            storage.set_string(&format!("{key}.unreadable"), value.clone());
+        }
+    }
+
+    fn migrate(mut self) -> Self {
+        if self.version > VERSION {
+            eprintln!(
+                "Settings were saved by a newer version ({:}), some may not have been read",
+                self.version
+            );
+        }
+
+        self.version = VERSION;
+        self
+    }
+
+    /// Lets a saved profile be picked, replacing the current hostname and denoise parameters.
+    pub fn profile_picker(&mut self, ui: &mut egui::Ui) {
+        if self.profiles.is_empty() {
+            return;
+        }
+
+        let current = self
+            .profiles
+            .iter()
+            .find(|profile| profile.hostname == self.hostname)
+            .map_or_else(|| self.hostname.clone(), |profile| profile.name.clone());
+
+        let mut selected = None;
+        egui::ComboBox::from_id_source("Profile")
+            .selected_text(current)
+            .show_ui(ui, |ui| {
+                for (index, profile) in self.profiles.iter().enumerate() {
+                    if ui.selectable_label(false, &profile.name).clicked() {
+                        selected = Some(index);
+                    }
+                }
+            });
+
+        if let Some(index) = selected {
+            let profile = &self.profiles[index];
+            self.hostname = profile.hostname.clone();
+            self.denoise = profile.denoise;
+        }
+    }
+
+    fn profiles_ui(&mut self, ui: &mut egui::Ui, new_profile_name: &mut String) {
+        ui.horizontal(|ui| {
+            ui.label("Profile: ");
+            self.profile_picker(ui);
+        });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(new_profile_name);
+
+            if ui.button("Save profile").clicked() && !new_profile_name.is_empty() {
+                let profile = Profile {
+                    name: std::mem::take(new_profile_name),
+                    hostname: self.hostname.clone(),
+                    denoise: self.denoise,
+                };
+
+                // Saving with an existing name replaces it
+                match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+                    Some(existing) => *existing = profile,
+                    None => self.profiles.push(profile),
+                }
+            }
+        });
+
+        let mut remove = None;
+        for (index, profile) in self.profiles.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{:} ({:})", profile.name, profile.hostname));
+                if ui.small_button("Delete").clicked() {
+                    remove = Some(index);
+                }
+            });
+        }
+        if let Some(index) = remove {
+            self.profiles.remove(index);
+        }
+    }
+
+    /// Returns true when the denoise parameters should be applied to the current points. The name
+    /// being typed for a new profile is kept by the caller, as it isn't a setting.
+    pub fn ui(
+        &mut self,
+        open: &mut bool,
+        new_profile_name: &mut String,
+        ctx: &egui::Context,
+        update_time: u128,
+        denoise_filter: &mut denoise::Filter,
+    ) -> bool {
+        let mut apply_denoise = false;
+
         egui::Window::new("Settings")
             .open(open)
             .collapsible(false)
@@ -36,8 +215,19 @@ impl Settings {
                     ui.text_edit_singleline(&mut self.hostname)
                         .labelled_by(hostname_label.id);
                 });
+                self.profiles_ui(ui, new_profile_name);
+                ui.separator();
                 let min_fmt = |x, _| format!("{:.0} mins", x / 60.0);
-                let ft_fmt = |x, _| format!("{:.0} ft", x);
+                let age_fmt = |x: f64, _| {
+                    if x < 2.0 * 60.0 * 60.0 {
+                        format!("{:.0} mins", x / 60.0)
+                    } else {
+                        format!("{:.1} hours", x / (60.0 * 60.0))
+                    }
+                };
+                let converter = self.altitude.converter();
+                let ft_fmt = |x, _| converter.format(x);
+                let ft_parse = |text: &str| converter.parse(text);
 
                 ui.horizontal(|ui| {
                     ui.add(
@@ -48,10 +238,30 @@ impl Settings {
                 });
                 ui.horizontal(|ui| {
                     ui.add(
-                        egui::Slider::new(&mut self.max_data_age, 60..=60 * 60)
-                            .custom_formatter(min_fmt)
+                        egui::Slider::new(&mut self.max_data_age, 60..=24 * 60 * 60)
+                            .logarithmic(true)
+                            .custom_formatter(age_fmt)
                             .text("Max data age"),
-                    );
+                    )
+                    .on_hover_text("Live sessions keep this much, including loaded recordings");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Autosave: ");
+                    if self.autosave_file.is_empty() {
+                        ui.label("Off");
+                    } else {
+                        ui.label(&self.autosave_file);
+                    }
+                    if ui.button("Browse").clicked() {
+                        if let Some(path) =
+                            tinyfiledialogs::save_file_dialog("Autosave to", &self.autosave_file)
+                        {
+                            self.autosave_file = path;
+                        }
+                    }
+                    if !self.autosave_file.is_empty() && ui.button("Off").clicked() {
+                        self.autosave_file.clear();
+                    }
                 });
                 ui.horizontal(|ui| {
                     ui.add(
@@ -61,6 +271,7 @@ impl Settings {
                         )
                         .clamp_to_range(true)
                         .custom_formatter(ft_fmt)
+                        .custom_parser(ft_parse)
                         .text("Y axis min"),
                     );
                 });
@@ -72,17 +283,201 @@ impl Settings {
                         )
                         .clamp_to_range(true)
                         .custom_formatter(ft_fmt)
+                        .custom_parser(ft_parse)
                         .text("Y axis max"),
                     );
                 });
                 ui.horizontal(|ui| {
                     ui.checkbox(&mut self.show_axis, "Show Y axis");
                 });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_vertical_rate, "Show vertical rate plot");
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_gnss, "Show barometric vs GNSS altitude plot");
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.utc_time, "Show times in UTC");
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(
+                        &mut self.label_aircraft,
+                        "Label traces matching Mode S aircraft",
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Aircraft database: ");
+                    if self.aircraft_database.is_empty() {
+                        ui.label("None");
+                    } else {
+                        ui.label(&self.aircraft_database);
+                    }
+                    if ui.button("Browse").clicked() {
+                        if let Some(path) = tinyfiledialogs::open_file_dialog(
+                            "Open aircraft database",
+                            &self.aircraft_database,
+                            None,
+                        ) {
+                            self.aircraft_database = path;
+                        }
+                    }
+                    if !self.aircraft_database.is_empty() && ui.button("Clear").clicked() {
+                        self.aircraft_database.clear();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Plot: ");
+                    ui.radio_value(&mut self.plot_mode, plot::Mode::Points, "Points");
+                    ui.radio_value(&mut self.plot_mode, plot::Mode::Heatmap, "Heatmap");
+                });
+                if self.plot_mode == plot::Mode::Heatmap {
+                    self.heatmap.ui(ui);
+                }
+
+                ui.separator();
+                ui.label("Altitude");
+                self.altitude.ui(ui);
+                ui.separator();
+                ui.label("Reference lines");
+                reference::lines_ui(ui, &mut self.reference_lines, converter);
+                ui.label("Altitude bands");
+                reference::bands_ui(ui, &mut self.altitude_bands, converter);
+                ui.separator();
+                ui.label("Denoise");
+                self.denoise.ui(ui);
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut denoise_filter.preview, "Preview removed points");
+                    apply_denoise = ui.button("Apply").clicked();
+                });
+                ui.separator();
+                ui.label("Alerts");
+                let denoise_applied = denoise_filter.is_applied() && !denoise_filter.preview;
+                alerts::rules_ui(ui, &mut self.alert_rules, converter, denoise_applied);
+                ui.horizontal(|ui| {
+                    ui.label("Log: ");
+                    if self.alert_log.is_empty() {
+                        ui.label("Off");
+                    } else {
+                        ui.label(&self.alert_log);
+                    }
+                    if ui.button("Browse").clicked() {
+                        if let Some(path) =
+                            tinyfiledialogs::save_file_dialog("Log alerts to", &self.alert_log)
+                        {
+                            self.alert_log = path;
+                        }
+                    }
+                    if !self.alert_log.is_empty() && ui.button("Off").clicked() {
+                        self.alert_log.clear();
+                    }
+                });
+                ui.separator();
 
                 // Data age must be >= to display age
                 self.max_data_age = max(self.max_data_age, self.max_display_age);
 
                 ui.label(format!("Update time: {:}ms", update_time));
             });
+
+        apply_denoise
+    }
+}
+
+// Reads a saved value, logging it and setting it aside if it can't be read rather than quietly
+// starting from the defaults
+fn read<T: DeserializeOwned>(
+    storage: &dyn eframe::Storage,
+    key: &str,
+    unreadable: &mut Vec<(String, String)>,
+) -> Option<T> {
+    let value = storage.get_string(key)?;
+    match ron::from_str(&value) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            eprintln!("Unable to read the saved {key}, it is kept as {key}.unreadable: {e}");
+            unreadable.push((key.to_owned(), value));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Storage(HashMap<String, String>);
+
+    impl eframe::Storage for Storage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_owned(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    fn profile(name: &str) -> Profile {
+        Profile {
+            name: name.to_owned(),
+            hostname: format!("{name}:30002"),
+            denoise: denoise::Params::default(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut settings = Settings {
+            hostname: "receiver:30002".to_owned(),
+            max_data_age: 120,
+            ..Settings::default()
+        };
+        settings.profiles.push(profile("roof"));
+        let mut storage = Storage::default();
+        settings.save(&mut storage);
+
+        let loaded = Settings::load(Some(&storage));
+        assert_eq!(loaded.version, VERSION);
+        assert_eq!(loaded.hostname, "receiver:30002");
+        assert_eq!(loaded.max_data_age, 120);
+        assert_eq!(loaded.profiles.len(), 1);
+        assert_eq!(loaded.profiles[0].hostname, "roof:30002");
+    }
+
+    #[test]
+    fn unreadable_settings_keep_profiles_and_are_set_aside() {
+        let mut settings = Settings::default();
+        settings.profiles.push(profile("roof"));
+        let mut storage = Storage::default();
+        settings.save(&mut storage);
+        // As if a later version changed the type of a field
+        let unreadable = "(max_data_age: \"an hour\")".to_owned();
+        storage.0.insert(STORAGE_KEY.to_owned(), unreadable.clone());
+
+        let loaded = Settings::load(Some(&storage));
+        assert_eq!(loaded.max_data_age, Settings::default().max_data_age);
+        assert_eq!(loaded.profiles.len(), 1);
+
+        loaded.save(&mut storage);
+        assert_eq!(storage.0["settings.unreadable"], unreadable);
+        assert_eq!(Settings::load(Some(&storage)).profiles.len(), 1);
+    }
+
+    #[test]
+    fn settings_saved_before_versions_load() {
+        let mut storage = Storage::default();
+        storage.0.insert(
+            STORAGE_KEY.to_owned(),
+            "(hostname: \"receiver:30002\")".to_owned(),
+        );
+
+        let loaded = Settings::load(Some(&storage));
+        assert_eq!(loaded.version, VERSION);
+        assert_eq!(loaded.hostname, "receiver:30002");
+        assert!(loaded.profiles.is_empty());
     }
 }
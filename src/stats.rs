@@ -0,0 +1,252 @@
+use crate::{data, store, units, view};
+use eframe::egui;
+use eframe::egui::plot::{Bar, BarChart, Line, Plot, PlotPoints};
+use eframe::egui::Color32;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant, SystemTime};
+use thousands::Separable;
+
+// Counting every point in a long window each frame is wasted work for numbers nobody can read
+// that fast
+const REFRESH: Duration = Duration::from_millis(250);
+const HISTOGRAM_BIN_FT: u32 = 1000;
+// Number of buckets the message rate is split into across the window
+const RATE_BUCKETS: usize = 60;
+const BAR_COLOR: Color32 = Color32::from_rgb(100, 200, 100);
+
+/// Statistics about the Mode A/C points in the visible time window, after denoising. Mode S
+/// altitudes come from identified aircraft rather than anonymous replies, so are left out.
+#[derive(Default)]
+pub struct Statistics {
+    computed_at: Option<Instant>,
+    summary: Option<Summary>,
+}
+
+struct Summary {
+    replies: usize,
+    rejected: usize,
+    // Not known for historical data, which only recorded valid replies
+    invalid: Option<usize>,
+    heights: Option<(u32, u32)>,
+    above: usize,
+    // Number of replies in each histogram bin, starting from 0 ft
+    histogram: Vec<u32>,
+    // Replies per minute at the middle of each bucket
+    rate: Vec<[f64; 2]>,
+}
+
+/// What the statistics are worked out from.
+pub struct Input<'a> {
+    pub points: &'a [data::Point],
+    pub keep: Option<&'a [bool]>,
+    pub invalid: Option<&'a VecDeque<SystemTime>>,
+    pub bounds: view::Bounds,
+    pub utc: bool,
+    pub converter: units::Converter,
+}
+
+impl Statistics {
+    pub fn ui(&mut self, ui: &mut egui::Ui, input: Input, threshold: &mut u32) {
+        let converter = input.converter;
+        ui.horizontal(|ui| {
+            ui.label("Count above: ");
+            if ui
+                .add(
+                    egui::DragValue::new(threshold)
+                        .speed(100)
+                        .clamp_range(0..=130_000)
+                        .custom_formatter(|x, _| converter.format(x))
+                        .custom_parser(|text| converter.parse(text)),
+                )
+                .changed()
+            {
+                self.computed_at = None;
+            }
+        });
+
+        let stale = self
+            .computed_at
+            .map_or(true, |computed_at| computed_at.elapsed() >= REFRESH);
+        if stale {
+            self.summary = Some(Summary::new(&input, *threshold));
+            self.computed_at = Some(Instant::now());
+        }
+
+        let summary = match &self.summary {
+            Some(summary) => summary,
+            None => return,
+        };
+
+        egui::Grid::new("Statistics").show(ui, |ui| {
+            ui.label("Mode A/C replies");
+            ui.label(summary.replies.separate_with_commas());
+            ui.end_row();
+
+            ui.label("Rejected");
+            ui.label(summary.rejected.separate_with_commas());
+            ui.end_row();
+
+            ui.label("Invalid Gillham codes");
+            match summary.invalid {
+                Some(invalid) => ui.label(invalid.separate_with_commas()),
+                None => ui.label("Not recorded"),
+            };
+            ui.end_row();
+
+            ui.label("Min altitude");
+            ui.label(summary.heights.map_or_else(
+                || "-".to_owned(),
+                |(min, _)| converter.format(f64::from(min)),
+            ));
+            ui.end_row();
+
+            ui.label("Max altitude");
+            ui.label(summary.heights.map_or_else(
+                || "-".to_owned(),
+                |(_, max)| converter.format(f64::from(max)),
+            ));
+            ui.end_row();
+
+            ui.label(format!(
+                "Above {:}",
+                converter.format(f64::from(*threshold))
+            ));
+            ui.label(summary.above.separate_with_commas());
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label("Altitude distribution");
+        let bars = summary
+            .histogram
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bin, count)| {
+                let height = bin as f64 * f64::from(HISTOGRAM_BIN_FT);
+                Bar::new(
+                    height + f64::from(HISTOGRAM_BIN_FT) / 2.0,
+                    f64::from(*count),
+                )
+                .width(f64::from(HISTOGRAM_BIN_FT))
+            })
+            .collect();
+        let y_fmt = move |y: f64, _range: &RangeInclusive<f64>| converter.format(y);
+        Plot::new("Altitude histogram")
+            .height(ui.available_height() * 0.6)
+            .include_x(0.0)
+            .include_y(input.bounds.min_y)
+            .include_y(input.bounds.max_y)
+            .y_axis_formatter(y_fmt)
+            .y_grid_spacer(converter.grid_spacer())
+            .allow_drag(false)
+            .allow_scroll(false)
+            .allow_zoom(false)
+            .allow_boxed_zoom(false)
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new(bars).horizontal().color(BAR_COLOR));
+            });
+
+        ui.separator();
+        ui.label("Replies per minute");
+        let rate = summary.rate.clone();
+        let utc = input.utc;
+        Plot::new("Message rate")
+            .include_y(0.0)
+            .x_axis_formatter(move |x, _range| view::format_time(x, utc))
+            .x_grid_spacer(view::time_grid_spacer)
+            .allow_drag(false)
+            .allow_scroll(false)
+            .allow_zoom(false)
+            .allow_boxed_zoom(false)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(rate)).color(BAR_COLOR));
+            });
+    }
+}
+
+impl Summary {
+    fn new(input: &Input, threshold: u32) -> Self {
+        let bounds = input.bounds;
+        let range = store::range(
+            input.points,
+            view::time_from_x(bounds.min_x),
+            view::time_from_x(bounds.max_x),
+        );
+
+        let bucket_width = ((bounds.max_x - bounds.min_x) / RATE_BUCKETS as f64).max(1.0);
+        let mut buckets = vec![0u32; RATE_BUCKETS];
+        let mut summary = Summary {
+            replies: 0,
+            rejected: 0,
+            invalid: None,
+            heights: None,
+            above: 0,
+            histogram: vec![],
+            rate: vec![],
+        };
+
+        for index in range {
+            if input.points[index].message.icao().is_some() {
+                continue;
+            }
+
+            // Points still waiting to be classified are left out until they have been
+            match input
+                .keep
+                .map_or(Some(true), |keep| keep.get(index).copied())
+            {
+                Some(true) => {}
+                Some(false) => {
+                    summary.rejected += 1;
+                    continue;
+                }
+                None => continue,
+            }
+
+            let point = &input.points[index];
+            summary.replies += 1;
+            summary.heights = Some(
+                summary
+                    .heights
+                    .map_or((point.height, point.height), |(min, max)| {
+                        (min.min(point.height), max.max(point.height))
+                    }),
+            );
+            if point.height > threshold {
+                summary.above += 1;
+            }
+
+            let bin = (point.height / HISTOGRAM_BIN_FT) as usize;
+            if summary.histogram.len() <= bin {
+                summary.histogram.resize(bin + 1, 0);
+            }
+            summary.histogram[bin] += 1;
+
+            let bucket = ((view::x_from_time(point.time) - bounds.min_x) / bucket_width) as usize;
+            if let Some(bucket) = buckets.get_mut(bucket) {
+                *bucket += 1;
+            }
+        }
+
+        summary.invalid = input.invalid.map(|invalid| {
+            let from = invalid.partition_point(|time| view::x_from_time(*time) < bounds.min_x);
+            let to = invalid.partition_point(|time| view::x_from_time(*time) <= bounds.max_x);
+            to.saturating_sub(from)
+        });
+
+        summary.rate = buckets
+            .iter()
+            .enumerate()
+            .map(|(bucket, count)| {
+                [
+                    bounds.min_x + (bucket as f64 + 0.5) * bucket_width,
+                    f64::from(*count) * 60.0 / bucket_width,
+                ]
+            })
+            .collect();
+
+        summary
+    }
+}
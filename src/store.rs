@@ -0,0 +1,205 @@
+use crate::data;
+use std::ops::Range;
+use std::time::SystemTime;
+
+// Only move the points down once this many have been pruned, so it happens rarely
+const COMPACT_AFTER: usize = 4096;
+
+/// Points ordered by time. Old points are pruned from the front by moving an offset rather than
+/// shifting every remaining point, so the points can still be borrowed as one slice.
+#[derive(Default)]
+pub struct Store {
+    points: Vec<data::Point>,
+    // Index of the oldest point that has not been pruned
+    start: usize,
+    // Changes whenever points are added anywhere other than the end of the live data
+    generation: u64,
+    // How many points have ever been pruned, so points can be followed as the front moves
+    pruned: usize,
+    // Newest of the points added in batches rather than as they arrived
+    newest_extended: Option<SystemTime>,
+}
+
+impl Store {
+    pub fn as_slice(&self) -> &[data::Point] {
+        &self.points[self.start..]
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len() - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// How many points have been pruned from the front since the store was created.
+    pub fn pruned(&self) -> usize {
+        self.pruned
+    }
+
+    /// The newest point added by [`Store::extend`], anything newer arrived live.
+    pub fn newest_extended(&self) -> Option<SystemTime> {
+        self.newest_extended
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.start = 0;
+        self.generation += 1;
+        self.newest_extended = None;
+    }
+
+    /// Points must be added in time order.
+    pub fn push(&mut self, point: data::Point) {
+        self.points.push(point);
+    }
+
+    /// Adds a batch of points, such as a loaded file. They can come from any time, a recording
+    /// loaded into a live session is merged in before the live points.
+    pub fn extend(&mut self, points: impl IntoIterator<Item = data::Point>) {
+        let from = self.points.len();
+        self.points.extend(points);
+        self.newest_extended = self.points[from..]
+            .iter()
+            .map(|point| point.time)
+            .max()
+            .max(self.newest_extended);
+
+        // Only the new points and the one before them need checking, the rest were in order
+        let unordered = self.points[from.saturating_sub(1).max(self.start)..]
+            .windows(2)
+            .any(|pair| pair[1].time < pair[0].time);
+        if unordered {
+            // The points are mostly in runs that are already sorted, which the stable sort takes
+            // advantage of
+            self.points[self.start..].sort_by_key(|point| point.time);
+        }
+        self.generation += 1;
+    }
+
+    /// Removes the points older than `time`, returning how many were removed.
+    pub fn prune_before(&mut self, time: SystemTime) -> usize {
+        let count = self.as_slice().partition_point(|point| point.time < time);
+        self.start += count;
+        self.pruned += count;
+
+        // Compacting once at least half of the points are pruned keeps pruning O(1) amortised
+        if self.start >= COMPACT_AFTER && self.start >= self.len() {
+            self.points.drain(..self.start);
+            self.start = 0;
+        }
+
+        count
+    }
+}
+
+/// Indices of the points between `start` and `end` inclusive.
+pub fn range(points: &[data::Point], start: SystemTime, end: SystemTime) -> Range<usize> {
+    let from = points.partition_point(|point| point.time < start);
+    let to = points.partition_point(|point| point.time <= end);
+    from..to.max(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::denoise::tests::point;
+
+    fn seconds(store: &Store) -> Vec<u32> {
+        store.as_slice().iter().map(|point| point.height).collect()
+    }
+
+    // Points whose height is the second they are at, to tell them apart
+    fn at(second: u32) -> data::Point {
+        point(f64::from(second), second)
+    }
+
+    #[test]
+    fn extend_keeps_points_in_order() {
+        let mut store = Store::default();
+        store.push(at(10));
+        store.push(at(11));
+        let generation = store.generation();
+        store.push(at(12));
+        assert_eq!(store.generation(), generation);
+
+        store.extend([at(13), at(14)]);
+        assert_eq!(seconds(&store), [10, 11, 12, 13, 14]);
+        assert_ne!(store.generation(), generation);
+
+        // An older recording, not in order itself
+        let generation = store.generation();
+        store.extend([at(5), at(3), at(11)]);
+        assert_eq!(seconds(&store), [3, 5, 10, 11, 11, 12, 13, 14]);
+        assert_ne!(store.generation(), generation);
+        assert_eq!(store.newest_extended(), Some(at(14).time));
+
+        let mut empty = Store::default();
+        empty.extend([at(2), at(1)]);
+        assert_eq!(seconds(&empty), [1, 2]);
+    }
+
+    #[test]
+    fn extend_sorts_only_what_was_not_pruned() {
+        let mut store = Store::default();
+        for second in 0..10 {
+            store.push(at(second));
+        }
+        assert_eq!(store.prune_before(at(5).time), 5);
+
+        store.extend([at(7)]);
+        assert_eq!(seconds(&store), [5, 6, 7, 7, 8, 9]);
+    }
+
+    #[test]
+    fn pruning_past_compaction() {
+        let total = COMPACT_AFTER as u32 * 3;
+        let mut store = Store::default();
+        for second in 0..total {
+            store.push(at(second));
+        }
+
+        // Not yet half of the points, so they stay where they are
+        let first = COMPACT_AFTER as u32;
+        assert_eq!(store.prune_before(at(first).time), COMPACT_AFTER);
+        assert_eq!(store.start, COMPACT_AFTER);
+        assert_eq!(store.as_slice()[0].height, first);
+
+        let second = first * 2 + 1;
+        assert_eq!(store.prune_before(at(second).time), COMPACT_AFTER + 1);
+        assert_eq!(store.start, 0);
+        assert_eq!(store.points.len(), store.len());
+        assert_eq!(store.len(), (total - second) as usize);
+        assert_eq!(store.as_slice()[0].height, second);
+        assert_eq!(store.pruned(), second as usize);
+
+        // Points carry on arriving after compacting
+        store.push(at(total));
+        assert_eq!(store.as_slice().last().unwrap().height, total);
+        assert_eq!(
+            store.prune_before(at(total + 1).time),
+            (total + 1 - second) as usize
+        );
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn ranges_include_both_ends() {
+        let points: Vec<data::Point> = [0, 1, 1, 2, 3, 5].into_iter().map(at).collect();
+        let time = |second| at(second).time;
+
+        assert_eq!(range(&points, time(1), time(3)), 1..5);
+        assert_eq!(range(&points, time(0), time(5)), 0..6);
+        assert_eq!(range(&points, time(4), time(4)), 5..5);
+        assert_eq!(range(&points, time(5), time(9)), 5..6);
+        assert_eq!(range(&points, time(6), time(9)), 6..6);
+        // Backwards windows are empty rather than inverted
+        assert_eq!(range(&points, time(3), time(1)), 4..4);
+        assert_eq!(range(&[], time(0), time(9)), 0..0);
+    }
+}
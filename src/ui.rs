@@ -1,20 +1,24 @@
 use eframe::egui;
 use settings::Settings;
-use std::cmp::min;
+use std::collections::VecDeque;
 use std::default::Default;
-use std::ops::Sub;
 use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, SystemTime};
 use thousands::Separable;
 
-use crate::denoise::denoise;
-use crate::plot::plot;
+use crate::denoise;
+use crate::plot::{plot, Layers, PlotState};
 use crate::settings;
-use crate::{adsb, data};
+use crate::{
+    adsb, alerts, autosave, correlate, data, database, emergency, gnss, stats, store, view,
+};
 
 pub struct Channels {
     pub plot_rx: mpsc::Receiver<data::Point>,
+    pub invalid_rx: mpsc::Receiver<SystemTime>,
+    pub emergency_rx: mpsc::Receiver<emergency::Reply>,
+    pub mode_s_rx: mpsc::Receiver<adsb::Report>,
     pub connection_state_rx: mpsc::Receiver<adsb::ConnectionState>,
 
     pub connect_tx: mpsc::Sender<String>,
@@ -30,105 +34,242 @@ pub fn run(channels: Channels) {
     eframe::run_native(
         "Plotter",
         options,
-        Box::new(|_cc| Box::new(Plotter::new(channels))),
+        Box::new(|cc| Box::new(Plotter::new(channels, Settings::load(cc.storage)))),
     );
 }
 
 struct HistoricalData {
-    oldest_point: SystemTime,
     newest_point: SystemTime,
+    playback: view::Playback,
 }
 
 struct Plotter {
-    points: Vec<data::Point>,
+    points: store::Store,
+    // Times of live replies with invalid altitude codes
+    invalid: VecDeque<SystemTime>,
     connection_state: adsb::ConnectionState,
     historical_data: Option<HistoricalData>,
     open_settings: bool,
-    time_offset: u32,
+    new_profile_name: String,
+    plot_state: PlotState,
+    denoise_filter: denoise::Filter,
+    statistics: stats::Statistics,
+    alerts: alerts::Alerts,
+    emergencies: emergency::Events,
+    correlator: correlate::Correlator,
+    gnss: gnss::Comparison,
+    database: database::Database,
+    autosave: autosave::Autosave,
 
     settings: Settings,
     channels: Channels,
 }
 
 impl Plotter {
-    fn new(channels: Channels) -> Self {
-        Self {
-            points: vec![],
+    fn new(channels: Channels, settings: Settings) -> Self {
+        let mut plotter = Self {
+            points: store::Store::default(),
+            invalid: VecDeque::new(),
             connection_state: adsb::ConnectionState::Disconnected,
             historical_data: None,
             open_settings: false,
-            time_offset: 0,
-            settings: Settings::default(),
+            new_profile_name: String::new(),
+            plot_state: PlotState::default(),
+            denoise_filter: denoise::Filter::default(),
+            statistics: stats::Statistics::default(),
+            alerts: alerts::Alerts::default(),
+            emergencies: emergency::Events::default(),
+            correlator: correlate::Correlator::default(),
+            gnss: gnss::Comparison::default(),
+            database: database::Database::default(),
+            autosave: autosave::Autosave::default(),
+            settings,
 
             channels,
+        };
+
+        // Carry on from the last session, connecting adds to it
+        if let Some(plot) = autosave::read(&plotter.settings.autosave_file) {
+            plotter.add_recording(plot);
         }
+
+        plotter
     }
 
     fn recv(&mut self) {
         for plot in self.channels.plot_rx.try_iter() {
             self.points.push(plot);
         }
+        self.invalid.extend(self.channels.invalid_rx.try_iter());
+        for reply in self.channels.emergency_rx.try_iter() {
+            self.emergencies.push(reply);
+        }
+        for report in self.channels.mode_s_rx.try_iter() {
+            self.gnss.push(&report);
+            self.correlator.push(report);
+        }
 
         if self.historical_data.is_none() {
             self.prune_old_data();
         }
 
         if let Ok(state) = self.channels.connection_state_rx.try_recv() {
+            // Don't lose what arrived since the last autosave
+            if matches!(self.connection_state, adsb::ConnectionState::Connected)
+                && matches!(state, adsb::ConnectionState::Disconnected)
+            {
+                self.autosave.flush(
+                    &self.settings.autosave_file,
+                    &self.points,
+                    self.plot_state.annotations.as_slice(),
+                    false,
+                );
+            }
             self.connection_state = state;
         }
     }
 
+    // Historical data is complete, so only live data needs to wait for more points to arrive
+    fn live_time(&self) -> Option<SystemTime> {
+        if self.historical_data.is_none() {
+            Some(SystemTime::now())
+        } else {
+            None
+        }
+    }
+
     fn prune_old_data(&mut self) {
-        let now = SystemTime::now();
-
-        // We know the points are always ordered, so we can save iterating over the whole vec.
-        loop {
-            if let Some(plot) = self.points.first() {
-                let seconds_ago = match now.duration_since(plot.time) {
-                    Ok(n) => n,
-                    Err(..) => panic!("Point is from the future?!"),
-                };
+        let max_age = Duration::from_secs(u64::from(self.settings.max_data_age));
 
-                if seconds_ago.as_secs() > u64::from(self.settings.max_data_age) {
-                    self.points.remove(0);
-                    continue;
-                }
-            }
+        if let Some(cutoff) = SystemTime::now().checked_sub(max_age) {
+            let removed = self.points.prune_before(cutoff);
+            self.denoise_filter.prune(removed);
 
-            break;
+            let invalid = self.invalid.partition_point(|time| *time < cutoff);
+            self.invalid.drain(..invalid);
+            self.correlator.prune(cutoff);
+            self.gnss.prune(cutoff);
         }
     }
 
     fn main_ui(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let start = SystemTime::now();
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            self.recv();
-            self.handle_key_press(ctx);
+        self.recv();
+        self.handle_key_press(ctx);
+
+        self.settings.altitude.refresh();
+        let converter = self.settings.altitude.converter();
+
+        let live_time = self.live_time();
+        self.denoise_filter
+            .update(self.points.as_slice(), &self.settings.denoise, live_time);
+
+        // Alerts are only for live data, not old recordings
+        if live_time.is_some() {
+            self.alerts.update(
+                &self.settings.alert_rules,
+                &self.points,
+                self.denoise_filter.applied_keep(),
+                &self.settings.alert_log,
+                self.settings.utc_time,
+                converter,
+            );
+        }
+        if let Some(now) = live_time {
+            self.emergencies.correlate(self.points.as_slice(), now);
+        }
+        if matches!(self.connection_state, adsb::ConnectionState::Connected) {
+            self.autosave.update(
+                &self.settings.autosave_file,
+                &self.points,
+                self.plot_state.annotations.as_slice(),
+            );
+        }
+        self.alerts.ui(ctx);
+        self.emergencies.ui(ctx, self.settings.utc_time, converter);
+        self.database.sync(&self.settings.aircraft_database, ctx);
+        self.plot_state
+            .aircraft
+            .ui(ctx, &self.correlator, &self.database);
+
+        // Use the newest point when showing historical data
+        // Or use the current time for live data
+        let data_x_age = self
+            .historical_data
+            .as_ref()
+            .map_or_else(SystemTime::now, |data| data.newest_point);
+
+        if self.settings.show_statistics {
+            egui::SidePanel::right("Statistics")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    let input = stats::Input {
+                        points: self.points.as_slice(),
+                        keep: self.denoise_filter.keep(),
+                        invalid: self.live_time().map(|_| &self.invalid),
+                        bounds: self.plot_state.view.bounds(&self.settings, data_x_age),
+                        utc: self.settings.utc_time,
+                        converter,
+                    };
+                    self.statistics
+                        .ui(ui, input, &mut self.settings.statistics_threshold);
+                });
+        }
+
+        if self.plot_state.annotations.open {
+            egui::SidePanel::left("Annotations")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    let bounds = self.plot_state.view.bounds(&self.settings, data_x_age);
+                    let jump = self.plot_state.annotations.ui(
+                        ui,
+                        view::time_from_x(bounds.max_x),
+                        self.settings.utc_time,
+                    );
+                    if let Some(time) = jump {
+                        self.plot_state.view.centre(bounds, view::x_from_time(time));
+                    }
+                });
+        }
 
+        egui::CentralPanel::default().show(ctx, |ui| {
             // Header
             ui.horizontal(|ui| {
                 if ui.button("Settings").clicked() {
                     self.open_settings = true;
                 }
+                ui.toggle_value(&mut self.settings.show_statistics, "Statistics");
+                ui.toggle_value(&mut self.plot_state.aircraft.open, "Aircraft");
+                let notes = if self.plot_state.annotations.is_empty() {
+                    "Notes".to_owned()
+                } else {
+                    format!("Notes: {:}", self.plot_state.annotations.len())
+                };
+                ui.toggle_value(&mut self.plot_state.annotations.open, notes);
+                if !self.emergencies.is_empty() {
+                    let label =
+                        egui::RichText::new(format!("Emergencies: {:}", self.emergencies.len()))
+                            .color(egui::Color32::from_rgb(255, 80, 80));
+                    ui.toggle_value(&mut self.emergencies.open, label);
+                }
 
                 let points_len = self.points.len();
 
                 match self.connection_state {
                     adsb::ConnectionState::Disconnected => {
+                        self.settings.profile_picker(ui);
+
+                        // Anything loaded stays, live data carries on from it
                         if ui.button("Connect").clicked() {
                             self.historical_data = None;
-                            self.points.clear();
+                            self.plot_state.view.reset();
 
                             self.channels
                                 .connect_tx
                                 .send(self.settings.hostname.clone())
                                 .expect("Unable to connect");
                         }
-
-                        if ui.button("Load").clicked() {
-                            self.load_historical();
-                        }
                     }
                     adsb::ConnectionState::Connecting => {
                         ui.spinner();
@@ -141,74 +282,157 @@ impl Plotter {
                     }
                 }
 
+                if ui.button("Load").clicked() {
+                    self.load_historical();
+                }
+
                 if points_len > 0 && ui.button("Save").clicked() {
-                    let points_copy = self.points.clone();
+                    let plot = data::Plot {
+                        points: self.points.as_slice().to_vec(),
+                        annotations: self.plot_state.annotations.as_slice().to_vec(),
+                    };
 
                     thread::spawn(|| {
-                        save_historical(points_copy);
+                        save_historical(plot);
                     });
                 }
 
+                if points_len > 0 && ui.button("Clear").clicked() {
+                    self.clear();
+                }
+
                 ui.label(format!("Points: {:}", points_len.separate_with_commas()));
-            });
 
-            ui.separator();
+                if let Some(removed) = self.denoise_filter.rejected() {
+                    ui.label(format!("Rejected: {:}", removed.separate_with_commas()));
+                }
 
-            if let Some(historical_data) = &self.historical_data {
-                let data_age_delta = historical_data
-                    .newest_point
-                    .duration_since(historical_data.oldest_point)
-                    .expect("Oldest point must be older");
+                if self.denoise_filter.is_applied() {
+                    ui.checkbox(&mut self.denoise_filter.show_rejected, "Show rejected");
 
-                let max_offset = data_age_delta.as_secs();
+                    if ui.button("Undo denoise").clicked() {
+                        self.denoise_filter.undo();
+                    }
+                } else if points_len > 0 && ui.button("Denoise").clicked() {
+                    self.denoise_filter.apply(self.settings.denoise);
+                }
 
-                if max_offset > u64::from(self.settings.max_display_age) {
-                    let key_scroll_amount = 60;
-                    if ctx.input().key_pressed(egui::Key::ArrowLeft) {
-                        self.time_offset += key_scroll_amount;
+                if self.historical_data.is_some() {
+                    if !self.plot_state.view.is_following() && ui.button("Reset view").clicked() {
+                        self.plot_state.view.reset();
                     }
-                    if ctx.input().key_pressed(egui::Key::ArrowRight)
-                        && self.time_offset > key_scroll_amount
-                    {
-                        self.time_offset -= key_scroll_amount;
+                } else if self.plot_state.view.is_following() {
+                    if ui.button("Pause").clicked() {
+                        self.pause();
                     }
+                } else if ui.button("Jump to live").clicked() {
+                    self.plot_state.view.reset();
+                }
+            });
 
-                    ui.horizontal(|ui| {
-                        ui.spacing_mut().slider_width = ui.available_width();
-                        ui.add(egui::Slider::new(
-                            &mut self.time_offset,
-                            0..=u32::try_from(
-                                max_offset - u64::from(self.settings.max_display_age),
-                            )
-                                .unwrap(),
-                        ));
-                    });
+            ui.separator();
+
+            let key_scroll_amount = 60.0;
+            let bounds = self.plot_state.view.bounds(&self.settings, data_x_age);
+            if ctx.input().key_pressed(egui::Key::ArrowLeft) {
+                self.plot_state.view.pan(bounds, -key_scroll_amount);
+            }
+            if ctx.input().key_pressed(egui::Key::ArrowRight) {
+                self.plot_state.view.pan(bounds, key_scroll_amount);
+            }
+
+            // Scrub back through everything retained, live data keeps arriving while paused
+            let newest = view::x_from_time(data_x_age);
+            let oldest = self
+                .points
+                .as_slice()
+                .first()
+                .map_or(newest, |point| view::x_from_time(point.time));
+            let earliest_end = oldest + (bounds.max_x - bounds.min_x);
+            if earliest_end < newest {
+                let utc = self.settings.utc_time;
+                let mut end = bounds.max_x;
+
+                ui.horizontal(|ui| {
+                    if let Some(historical_data) = &mut self.historical_data {
+                        historical_data.playback.ui(
+                            ui,
+                            &mut self.plot_state.view,
+                            bounds,
+                            earliest_end,
+                            newest,
+                        );
+                    }
 
-                    self.time_offset = min(
-                        self.time_offset,
-                        u32::try_from(max_offset - u64::from(self.settings.max_display_age))
-                            .unwrap(),
+                    ui.spacing_mut().slider_width = ui.available_width() - 80.0;
+                    let slider = egui::Slider::new(&mut end, earliest_end..=newest)
+                        .clamp_to_range(true)
+                        .custom_formatter(|x, _| view::format_time(x, utc));
+                    if ui.add(slider).changed() {
+                        self.plot_state.view.scrub(bounds, end);
+                    }
+                });
+            }
+
+            if let Some(historical_data) = &mut self.historical_data {
+                if historical_data.playback.playing {
+                    let seconds = f64::from(ctx.input().unstable_dt);
+                    let bounds = self.plot_state.view.bounds(&self.settings, data_x_age);
+                    historical_data.playback.advance(
+                        &mut self.plot_state.view,
+                        bounds,
+                        newest,
+                        seconds,
                     );
+                    ctx.request_repaint();
                 }
             }
 
-            // Use the newest point when showing historical data
-            // Or use the current time for live data
-            let data_x_age = self
-                .historical_data
-                .as_mut()
-                .map_or_else(SystemTime::now, |data| {
-                    data.newest_point
-                        .sub(Duration::from_secs(u64::from(self.time_offset)))
-                });
+            if self.settings.label_aircraft {
+                let bounds = self.plot_state.view.bounds(&self.settings, data_x_age);
+                self.correlator.update(
+                    self.points.as_slice(),
+                    self.denoise_filter.keep(),
+                    view::time_from_x(bounds.min_x),
+                    view::time_from_x(bounds.max_x),
+                );
+            }
 
-            plot(ui, &self.points, &self.settings, &data_x_age);
+            let layers = Layers {
+                keep: self.denoise_filter.keep(),
+                generation: self.denoise_filter.generation(),
+                show_rejected: self.denoise_filter.show_rejected,
+                preview: self.denoise_filter.preview,
+                correlator: &self.correlator,
+                database: &self.database,
+                gnss: &self.gnss,
+                label_aircraft: self.settings.label_aircraft,
+            };
+
+            plot(
+                ui,
+                &self.points,
+                &self.settings,
+                &data_x_age,
+                layers,
+                &mut self.plot_state,
+            );
         });
 
         let end = SystemTime::now();
         let update_time = end.duration_since(start).unwrap().as_millis();
 
-        self.settings.ui(&mut self.open_settings, ctx, update_time);
+        let apply_denoise = self.settings.ui(
+            &mut self.open_settings,
+            &mut self.new_profile_name,
+            ctx,
+            update_time,
+            &mut self.denoise_filter,
+        );
+
+        if apply_denoise {
+            self.denoise_filter.apply(self.settings.denoise);
+        }
     }
 
     fn load_historical(&mut self) {
@@ -228,9 +452,13 @@ impl Plotter {
         };
 
         if let Some(plot) = result {
-            for x in plot.points {
-                self.points.push(x);
-            }
+            self.add_recording(plot);
+        }
+
+        // While connected the recording becomes the start of the live session, only the points
+        // within the max data age are kept
+        if !matches!(self.connection_state, adsb::ConnectionState::Disconnected) {
+            return;
         }
 
         if self.points.is_empty() {
@@ -244,26 +472,81 @@ impl Plotter {
             tinyfiledialogs::YesNo::No,
         ) == tinyfiledialogs::YesNo::Yes
         {
-            self.points = denoise(&self.points);
+            self.denoise_filter.apply(self.settings.denoise);
         }
 
-        let oldest_point = self
-            .points
-            .first()
-            .map_or_else(SystemTime::now, |point| point.time);
-
         let newest_point = self
             .points
+            .as_slice()
             .last()
             .map_or_else(SystemTime::now, |point| point.time);
 
+        self.plot_state.view.reset();
         self.historical_data = Some(HistoricalData {
-            oldest_point,
             newest_point,
+            playback: view::Playback::default(),
         });
     }
 
+    // The points can land before ones already classified, so denoising starts again
+    fn add_recording(&mut self, plot: data::Plot) {
+        self.points.extend(plot.points);
+        self.plot_state.annotations.extend(plot.annotations);
+        self.denoise_filter.restart();
+    }
+
+    // Connecting and loading add to the session, so starting afresh is done here
+    fn clear(&mut self) {
+        if tinyfiledialogs::message_box_yes_no(
+            "Plotter",
+            "Clear all points and notes?",
+            tinyfiledialogs::MessageBoxIcon::Question,
+            tinyfiledialogs::YesNo::No,
+        ) == tinyfiledialogs::YesNo::No
+        {
+            return;
+        }
+
+        self.historical_data = None;
+        self.points.clear();
+        self.invalid.clear();
+        self.correlator.clear();
+        self.gnss.clear();
+        self.plot_state.annotations.clear();
+        self.plot_state.view.reset();
+    }
+
+    fn pause(&mut self) {
+        let bounds = self
+            .plot_state
+            .view
+            .bounds(&self.settings, SystemTime::now());
+        self.plot_state.view.pause(bounds);
+    }
+
     fn handle_key_press(&mut self, ctx: &egui::Context) {
+        // Space toggles pausing live data, unless it is being typed into a text box
+        if ctx.input().key_pressed(egui::Key::Space)
+            && !ctx.wants_keyboard_input()
+            && self.historical_data.is_none()
+        {
+            if self.plot_state.view.is_following() {
+                self.pause();
+            } else {
+                self.plot_state.view.reset();
+            }
+        }
+        // B bookmarks the end of the view, which is now while following live data
+        if ctx.input().key_pressed(egui::Key::B) && !ctx.wants_keyboard_input() {
+            let end = self
+                .historical_data
+                .as_ref()
+                .map_or_else(SystemTime::now, |data| data.newest_point);
+            let bounds = self.plot_state.view.bounds(&self.settings, end);
+            self.plot_state
+                .annotations
+                .add_bookmark(view::time_from_x(bounds.max_x));
+        }
         if ctx.input().key_pressed(egui::Key::PlusEquals) && self.settings.max_display_age >= 120 {
             self.settings.max_display_age -= 60;
         }
@@ -273,7 +556,7 @@ impl Plotter {
     }
 }
 
-fn save_historical(points: Vec<data::Point>) {
+fn save_historical(plot: data::Plot) {
     let default_path = default_file_path();
     let file_path = tinyfiledialogs::save_file_dialog("Open Data file", &default_path);
 
@@ -281,12 +564,7 @@ fn save_historical(points: Vec<data::Point>) {
         return;
     }
 
-    if let Err(e) = data::write(
-        file_path.unwrap().as_str(),
-        data::Plot {
-            points,
-        },
-    ) {
+    if let Err(e) = data::write(file_path.unwrap().as_str(), &plot) {
         eprintln!("Failed to write: {e}");
     }
 }
@@ -304,4 +582,19 @@ impl eframe::App for Plotter {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         self.main_ui(ctx, frame);
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.settings.save(storage);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if matches!(self.connection_state, adsb::ConnectionState::Connected) {
+            self.autosave.flush(
+                &self.settings.autosave_file,
+                &self.points,
+                self.plot_state.annotations.as_slice(),
+                true,
+            );
+        }
+    }
 }
@@ -0,0 +1,367 @@
+use eframe::egui;
+use eframe::egui::plot::{log_grid_spacer, GridInput, GridMark};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::{Duration, Instant};
+use thousands::Separable;
+
+// Mode C and Mode S report pressure altitude against the standard atmosphere
+const STANDARD_QNH: f64 = 1013.25;
+// How much a hectopascal of pressure is worth in height near sea level. Good enough for an
+// approximate true altitude, which also ignores temperature.
+const FEET_PER_HPA: f64 = 27.0;
+const METRES_PER_FOOT: f64 = 0.3048;
+const HPA_PER_INHG: f64 = 33.8639;
+// METAR files are usually rewritten by a cron job, so they are read again now and then
+const METAR_REFRESH: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unit {
+    #[default]
+    Feet,
+    Metres,
+    FlightLevel,
+}
+
+/// Where the QNH used to correct pressure altitudes comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QnhSource {
+    #[default]
+    Standard,
+    Manual,
+    Metar,
+}
+
+/// How altitudes are shown.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Altitude {
+    pub unit: Unit,
+    pub qnh_source: QnhSource,
+    // In hPa
+    pub manual_qnh: f64,
+    pub metar_file: String,
+
+    #[serde(skip)]
+    metar: Option<Metar>,
+}
+
+// The QNH last read from the METAR file
+#[derive(Debug)]
+struct Metar {
+    path: String,
+    read_at: Instant,
+    qnh: Result<f64, String>,
+}
+
+impl Default for Altitude {
+    fn default() -> Self {
+        Altitude {
+            unit: Unit::default(),
+            qnh_source: QnhSource::default(),
+            manual_qnh: STANDARD_QNH,
+            metar_file: String::new(),
+            metar: None,
+        }
+    }
+}
+
+impl Altitude {
+    /// Reads the METAR file again if it has changed or it's been a while.
+    pub fn refresh(&mut self) {
+        if self.qnh_source != QnhSource::Metar || self.metar_file.is_empty() {
+            return;
+        }
+
+        let stale = self.metar.as_ref().map_or(true, |metar| {
+            metar.path != self.metar_file || metar.read_at.elapsed() >= METAR_REFRESH
+        });
+        if stale {
+            let qnh = fs::read_to_string(&self.metar_file)
+                .map_err(|e| e.to_string())
+                .and_then(|metar| parse_metar_qnh(&metar).ok_or_else(|| "No QNH".to_owned()));
+            if let Err(e) = &qnh {
+                eprintln!("Unable to read QNH from {:}: {e}", self.metar_file);
+            }
+
+            self.metar = Some(Metar {
+                path: self.metar_file.clone(),
+                read_at: Instant::now(),
+                qnh,
+            });
+        }
+    }
+
+    fn qnh(&self) -> Option<f64> {
+        match self.qnh_source {
+            QnhSource::Standard => None,
+            QnhSource::Manual => Some(self.manual_qnh),
+            QnhSource::Metar => self
+                .metar
+                .as_ref()
+                .filter(|metar| metar.path == self.metar_file)
+                .and_then(|metar| metar.qnh.as_ref().ok().copied()),
+        }
+    }
+
+    pub fn converter(&self) -> Converter {
+        Converter {
+            unit: self.unit,
+            qnh: self.qnh(),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Units: ");
+            ui.radio_value(&mut self.unit, Unit::Feet, "Feet");
+            ui.radio_value(&mut self.unit, Unit::Metres, "Metres");
+            ui.radio_value(&mut self.unit, Unit::FlightLevel, "Flight levels");
+        });
+        ui.horizontal(|ui| {
+            ui.label("QNH: ");
+            ui.radio_value(&mut self.qnh_source, QnhSource::Standard, "Standard");
+            ui.radio_value(&mut self.qnh_source, QnhSource::Manual, "Manual");
+            ui.radio_value(&mut self.qnh_source, QnhSource::Metar, "METAR file");
+        });
+
+        match self.qnh_source {
+            QnhSource::Standard => {}
+            QnhSource::Manual => {
+                ui.add(
+                    egui::DragValue::new(&mut self.manual_qnh)
+                        .speed(0.1)
+                        .clamp_range(900.0..=1100.0)
+                        .suffix(" hPa"),
+                );
+            }
+            QnhSource::Metar => {
+                ui.horizontal(|ui| {
+                    ui.label(if self.metar_file.is_empty() {
+                        "None"
+                    } else {
+                        &self.metar_file
+                    });
+                    if ui.button("Browse").clicked() {
+                        if let Some(path) =
+                            tinyfiledialogs::open_file_dialog("Open METAR", &self.metar_file, None)
+                        {
+                            self.metar_file = path;
+                        }
+                    }
+                });
+                match self.metar.as_ref().map(|metar| &metar.qnh) {
+                    Some(Ok(qnh)) => ui.label(format!("{:.1} hPa", qnh)),
+                    Some(Err(e)) => ui.label(format!("Not available: {e}")),
+                    None => ui.label("Not read yet"),
+                };
+            }
+        }
+
+        if self.unit == Unit::FlightLevel && self.qnh_source != QnhSource::Standard {
+            ui.label("Flight levels always use the standard QNH");
+        }
+    }
+}
+
+/// Turns pressure altitudes in feet into the chosen unit, corrected for QNH.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Converter {
+    unit: Unit,
+    qnh: Option<f64>,
+}
+
+impl Converter {
+    // Flight levels are pressure altitudes by definition
+    fn correction(self) -> f64 {
+        match (self.unit, self.qnh) {
+            (Unit::FlightLevel, _) | (_, None) => 0.0,
+            (_, Some(qnh)) => (qnh - STANDARD_QNH) * FEET_PER_HPA,
+        }
+    }
+
+    fn scale(self) -> f64 {
+        match self.unit {
+            Unit::Feet => 1.0,
+            Unit::Metres => METRES_PER_FOOT,
+            Unit::FlightLevel => 0.01,
+        }
+    }
+
+    fn display(self, feet: f64) -> f64 {
+        (feet + self.correction()) * self.scale()
+    }
+
+    fn feet(self, value: f64) -> f64 {
+        value / self.scale() - self.correction()
+    }
+
+    pub fn format(self, feet: f64) -> String {
+        let value = self.display(feet).round();
+        match self.unit {
+            Unit::Feet => format!("{:}ft", value.separate_with_commas()),
+            Unit::Metres => format!("{:}m", value.separate_with_commas()),
+            Unit::FlightLevel => format!("FL{:03}", value),
+        }
+    }
+
+    /// Reads a value typed into a slider in the display unit, with or without the unit.
+    pub fn parse(self, text: &str) -> Option<f64> {
+        let text = text.trim().to_lowercase().replace(',', "");
+        let number = text
+            .trim_start_matches("fl")
+            .trim_end_matches("ft")
+            .trim_end_matches('m')
+            .trim();
+        number.parse::<f64>().ok().map(|value| self.feet(value))
+    }
+
+    /// Formats a height that isn't a pressure altitude, like a GNSS height or a difference, so
+    /// no QNH correction applies. Flight levels only make sense for pressure altitudes, so those
+    /// stay in feet.
+    pub fn format_height(self, feet: f64) -> String {
+        match self.unit {
+            Unit::Metres => format!(
+                "{:}m",
+                (feet * METRES_PER_FOOT).round().separate_with_commas()
+            ),
+            Unit::Feet | Unit::FlightLevel => format!("{:}ft", feet.round().separate_with_commas()),
+        }
+    }
+
+    /// Grid lines at round values of the display unit.
+    pub fn grid_spacer(self) -> impl Fn(GridInput) -> Vec<GridMark> {
+        let spacer = log_grid_spacer(10);
+        move |input: GridInput| {
+            let display = GridInput {
+                bounds: (self.display(input.bounds.0), self.display(input.bounds.1)),
+                base_step_size: input.base_step_size * self.scale(),
+            };
+            spacer(display)
+                .into_iter()
+                .map(|mark| GridMark {
+                    value: self.feet(mark.value),
+                    step_size: mark.step_size / self.scale(),
+                })
+                .collect()
+        }
+    }
+}
+
+// The altimeter group, either Q followed by hPa or A followed by hundredths of inches of mercury
+fn parse_metar_qnh(metar: &str) -> Option<f64> {
+    metar
+        .split_whitespace()
+        .take_while(|group| *group != "RMK")
+        .find_map(|group| {
+            let (unit, value) = group.split_at(group.char_indices().nth(1)?.0);
+            if value.len() != 4 || !value.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let value: f64 = value.parse().ok()?;
+            match unit {
+                "Q" => Some(value),
+                "A" => Some(value / 100.0 * HPA_PER_INHG),
+                _ => None,
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn converter(unit: Unit, qnh: Option<f64>) -> Converter {
+        Altitude {
+            unit,
+            qnh_source: qnh.map_or(QnhSource::Standard, |_| QnhSource::Manual),
+            manual_qnh: qnh.unwrap_or(STANDARD_QNH),
+            ..Altitude::default()
+        }
+        .converter()
+    }
+
+    #[test]
+    fn reads_qnh_from_metars() {
+        let hpa = parse_metar_qnh("EGLL 191220Z 24012KT 9999 FEW030 14/08 Q1009 NOSIG").unwrap();
+        assert_eq!(hpa, 1009.0);
+
+        let inhg =
+            parse_metar_qnh("KJFK 191251Z 31015G22KT 10SM FEW050 12/M02 A2992 RMK AO2").unwrap();
+        assert!((inhg - 1013.2).abs() < 0.1, "{inhg}");
+    }
+
+    #[test]
+    fn ignores_missing_and_garbled_qnh() {
+        assert_eq!(parse_metar_qnh(""), None);
+        assert_eq!(
+            parse_metar_qnh("EGLL 191220Z 24012KT 9999 FEW030 14/08"),
+            None
+        );
+        assert_eq!(parse_metar_qnh("EGLL 191220Z Q//// NOSIG"), None);
+        assert_eq!(parse_metar_qnh("EGLL Q101 Q10090 Q1O09 QNH"), None);
+        // Remarks can hold numbers that look like an altimeter setting
+        assert_eq!(parse_metar_qnh("KJFK 191251Z 10SM RMK A2992"), None);
+        // A lone letter or multibyte text doesn't trip up splitting the group
+        assert_eq!(parse_metar_qnh("Q É1013 Ü"), None);
+    }
+
+    #[test]
+    fn formats_each_unit() {
+        assert_eq!(converter(Unit::Feet, None).format(35_000.0), "35,000ft");
+        assert_eq!(converter(Unit::Metres, None).format(10_000.0), "3,048m");
+        assert_eq!(converter(Unit::FlightLevel, None).format(35_000.0), "FL350");
+        assert_eq!(converter(Unit::FlightLevel, None).format(5_000.0), "FL050");
+    }
+
+    #[test]
+    fn rounds_to_the_nearest_flight_level() {
+        let converter = converter(Unit::FlightLevel, None);
+        assert_eq!(converter.format(34_949.0), "FL349");
+        assert_eq!(converter.format(34_950.0), "FL350");
+        assert_eq!(converter.format(35_049.0), "FL350");
+    }
+
+    #[test]
+    fn corrects_for_qnh() {
+        // A low QNH puts aircraft lower than their pressure altitude
+        let low = converter(Unit::Feet, Some(STANDARD_QNH - 10.0));
+        assert_eq!(low.format(5_000.0), "4,730ft");
+        let high = converter(Unit::Metres, Some(STANDARD_QNH + 10.0));
+        assert_eq!(high.format(5_000.0), "1,606m");
+
+        // Flight levels are always against the standard QNH
+        let flight_level = converter(Unit::FlightLevel, Some(STANDARD_QNH - 10.0));
+        assert_eq!(flight_level.format(5_000.0), "FL050");
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        let converters = [
+            converter(Unit::Feet, None),
+            converter(Unit::Metres, None),
+            converter(Unit::FlightLevel, None),
+            converter(Unit::Feet, Some(990.0)),
+            converter(Unit::Metres, Some(1030.0)),
+        ];
+        for converter in converters {
+            for feet in [0.0, 1_000.0, 35_000.0, 65_000.0] {
+                let parsed = converter.parse(&converter.format(feet)).unwrap();
+                // Within the rounding of the display unit
+                assert!(
+                    (parsed - feet).abs() <= 50.0,
+                    "{converter:?} {feet} {parsed}"
+                );
+            }
+        }
+
+        let feet = converter(Unit::Feet, None);
+        assert_eq!(feet.parse(" 35000 "), Some(35_000.0));
+        assert_eq!(
+            converter(Unit::FlightLevel, None).parse("fl350"),
+            Some(35_000.0)
+        );
+        assert_eq!(feet.parse("high"), None);
+        assert_eq!(feet.parse(""), None);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::settings;
+use chrono::{Local, TimeZone, Utc};
+use eframe::egui;
+use eframe::egui::plot::{GridInput, GridMark, PlotPoint, PlotUi};
+use eframe::egui::PointerButton;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Steps the time axis grid can use, in seconds
+const TIME_STEPS: [f64; 18] = [
+    1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1800.0, 3600.0, 7200.0,
+    10800.0, 21600.0, 43200.0, 86400.0,
+];
+// How much a single step of the mouse wheel zooms by
+const SCROLL_ZOOM_SPEED: f32 = 1.0 / 200.0;
+// The end of 9999, as far along the time axis as times go
+const MAX_X: f64 = 253_402_300_799.0;
+// How far out the view can be zoomed, a century of time and well above anything that flies
+const MAX_SPAN_X: f64 = 100.0 * 365.25 * 86400.0;
+const MAX_SPAN_Y: f64 = 1_000_000.0;
+
+/// The visible area of the plot, time in seconds since the unix epoch against height in feet.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Bounds {
+    pub min_x: f64,
+    pub max_x: f64,
+    pub min_y: f64,
+    pub max_y: f64,
+}
+
+impl Bounds {
+    pub fn contains_y(&self, y: f64) -> bool {
+        y >= self.min_y && y <= self.max_y
+    }
+
+    fn translate(&mut self, x: f64, y: f64) {
+        self.min_x += x;
+        self.max_x += x;
+        self.min_y += y;
+        self.max_y += y;
+    }
+
+    fn zoom(&mut self, factor: egui::Vec2, around: PlotPoint) {
+        let factor_x = f64::from(factor.x);
+        let factor_y = f64::from(factor.y);
+        self.min_x = around.x - (around.x - self.min_x) / factor_x;
+        self.max_x = around.x + (self.max_x - around.x) / factor_x;
+        self.min_y = around.y - (around.y - self.min_y) / factor_y;
+        self.max_y = around.y + (self.max_y - around.y) / factor_y;
+    }
+
+    // Shrinks the bounds around their centre to no more than the largest span
+    fn limit_span(&mut self) {
+        let limit = |min: &mut f64, max: &mut f64, span: f64| {
+            if *max - *min > span {
+                let centre = *min / 2.0 + *max / 2.0;
+                *min = centre - span / 2.0;
+                *max = centre + span / 2.0;
+            }
+        };
+        limit(&mut self.min_x, &mut self.max_x, MAX_SPAN_X);
+        limit(&mut self.min_y, &mut self.max_y, MAX_SPAN_Y);
+    }
+}
+
+/// What the pointer was doing over the plot, gathered while it is being built.
+pub struct Navigation {
+    // Plot units per screen point on each axis
+    per_point: [f64; 2],
+    pointer: Option<PlotPoint>,
+}
+
+impl Navigation {
+    pub fn new(plot_ui: &PlotUi) -> Self {
+        let bounds = plot_ui.plot_bounds();
+        let min = plot_ui.screen_from_plot(PlotPoint::new(bounds.min()[0], bounds.min()[1]));
+        let max = plot_ui.screen_from_plot(PlotPoint::new(bounds.max()[0], bounds.max()[1]));
+
+        Navigation {
+            per_point: [
+                bounds.width() / f64::from((max.x - min.x).max(1.0)),
+                bounds.height() / f64::from((min.y - max.y).max(1.0)),
+            ],
+            pointer: plot_ui.pointer_coordinate(),
+        }
+    }
+
+    pub fn per_point(&self) -> [f64; 2] {
+        self.per_point
+    }
+}
+
+/// Zooming and panning of the plot.
+///
+/// Until the user moves the view it follows the newest data using the display settings. Once it
+/// has been moved it stays put, even as new live data arrives, until it is reset.
+#[derive(Default)]
+pub struct View {
+    bounds: Option<Bounds>,
+    zoom_start: Option<PlotPoint>,
+}
+
+impl View {
+    pub fn bounds(&self, settings: &settings::Settings, end: SystemTime) -> Bounds {
+        self.bounds.unwrap_or_else(|| {
+            let max_x = x_from_time(end);
+            Bounds {
+                min_x: max_x - f64::from(settings.max_display_age),
+                max_x,
+                min_y: f64::from(settings.min_display_height),
+                max_y: f64::from(settings.max_display_height),
+            }
+        })
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.bounds.is_none()
+    }
+
+    pub fn reset(&mut self) {
+        self.bounds = None;
+        self.zoom_start = None;
+    }
+
+    /// Stops following the data, keeping the current view while live data carries on arriving.
+    pub fn pause(&mut self, current: Bounds) {
+        self.bounds = Some(current);
+    }
+
+    /// Moves the view so it ends at `end`, keeping its width and height.
+    pub fn scrub(&mut self, current: Bounds, end: f64) {
+        self.pan(current, end - current.max_x);
+    }
+
+    /// Moves the view so `x` is in the middle of it.
+    pub fn centre(&mut self, current: Bounds, x: f64) {
+        self.scrub(current, x + (current.max_x - current.min_x) / 2.0);
+    }
+
+    pub fn pan(&mut self, current: Bounds, seconds: f64) {
+        let mut bounds = current;
+        bounds.translate(seconds, 0.0);
+        self.bounds = Some(bounds);
+    }
+
+    /// The box being dragged out to zoom into, if any.
+    pub fn zoom_box(&self, navigation: &Navigation) -> Option<(PlotPoint, PlotPoint)> {
+        self.zoom_start.zip(navigation.pointer)
+    }
+
+    /// Drag to pan, scroll to zoom the time axis (shift + scroll for height, ctrl + scroll or
+    /// pinch for both), right drag to box zoom and double click to go back to following the data.
+    /// Shift + drag is left alone for selecting regions.
+    pub fn interact(
+        &mut self,
+        current: Bounds,
+        response: &egui::Response,
+        navigation: &Navigation,
+    ) {
+        if response.double_clicked() {
+            self.reset();
+            return;
+        }
+
+        let (shift, scroll, zoom) = {
+            let input = response.ctx.input();
+            (
+                input.modifiers.shift,
+                input.scroll_delta,
+                input.zoom_delta_2d(),
+            )
+        };
+        let [per_x, per_y] = navigation.per_point;
+        let mut bounds = current;
+
+        if response.dragged_by(PointerButton::Primary) && !shift {
+            let delta = response.drag_delta();
+            bounds.translate(-f64::from(delta.x) * per_x, f64::from(delta.y) * per_y);
+        }
+
+        if let (true, Some(pointer)) = (response.hovered(), navigation.pointer) {
+            let scroll = scroll.x + scroll.y;
+            if scroll != 0.0 {
+                let factor = (scroll * SCROLL_ZOOM_SPEED).exp();
+                let factor = if shift {
+                    egui::vec2(1.0, factor)
+                } else {
+                    egui::vec2(factor, 1.0)
+                };
+                bounds.zoom(factor, pointer);
+            }
+
+            if zoom != egui::Vec2::splat(1.0) {
+                bounds.zoom(zoom, pointer);
+            }
+        }
+
+        if response.drag_started() && response.dragged_by(PointerButton::Secondary) {
+            self.zoom_start = navigation.pointer;
+        }
+        if response.drag_released() {
+            if let Some((start, end)) = self.zoom_box(navigation) {
+                if start.x != end.x && start.y != end.y {
+                    bounds = Bounds {
+                        min_x: start.x.min(end.x),
+                        max_x: start.x.max(end.x),
+                        min_y: start.y.min(end.y),
+                        max_y: start.y.max(end.y),
+                    };
+                }
+            }
+            self.zoom_start = None;
+        }
+
+        bounds.limit_span();
+        if bounds != current {
+            self.bounds = Some(bounds);
+        }
+    }
+}
+
+/// Replays historical data by moving the end of the view along at a multiple of real time.
+pub struct Playback {
+    pub playing: bool,
+    pub speed: f64,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback {
+            playing: false,
+            speed: 1.0,
+        }
+    }
+}
+
+impl Playback {
+    /// Play/pause and speed controls. Playing from the end of the data starts again from `start`.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        view: &mut View,
+        current: Bounds,
+        start: f64,
+        end: f64,
+    ) {
+        let label = if self.playing { "Pause" } else { "Play" };
+        if ui.button(label).clicked() {
+            self.playing = !self.playing;
+
+            if self.playing && current.max_x >= end {
+                view.scrub(current, start);
+            }
+        }
+
+        ui.add(
+            egui::Slider::new(&mut self.speed, 1.0..=1000.0)
+                .logarithmic(true)
+                .clamp_to_range(true)
+                .custom_formatter(|x, _| format!("{:.0}×", x))
+                .text("Speed"),
+        );
+    }
+
+    /// Moves the view on by `seconds` of real time, stopping at the end of the data.
+    pub fn advance(&mut self, view: &mut View, current: Bounds, end: f64, seconds: f64) {
+        if !self.playing {
+            return;
+        }
+
+        let next = (current.max_x + seconds * self.speed).min(end);
+        view.scrub(current, next);
+
+        if next >= end {
+            self.playing = false;
+        }
+    }
+}
+
+pub fn x_from_time(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0.0, |duration| duration.as_secs_f64())
+}
+
+/// The time at a position on the time axis. Positions outside the years 1970 to 9999, including
+/// infinite and NaN ones from extreme zooming, are clamped rather than panicking.
+pub fn time_from_x(x: f64) -> SystemTime {
+    let x = if x.is_nan() { 0.0 } else { x.clamp(0.0, MAX_X) };
+    UNIX_EPOCH + Duration::from_secs_f64(x)
+}
+
+pub fn format_time(x: f64, utc: bool) -> String {
+    let seconds = x.floor() as i64;
+    let formatted = if utc {
+        Utc.timestamp_opt(seconds, 0)
+            .single()
+            .map(|time| time.format("%H:%M:%S").to_string())
+    } else {
+        Local
+            .timestamp_opt(seconds, 0)
+            .single()
+            .map(|time| time.format("%H:%M:%S").to_string())
+    };
+
+    formatted.unwrap_or_default()
+}
+
+/// The full date and time down to the millisecond, for inspecting individual points.
+pub fn format_timestamp(time: SystemTime, utc: bool) -> String {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis());
+    let millis = i64::try_from(millis).unwrap_or_default();
+    let formatted = if utc {
+        Utc.timestamp_millis_opt(millis)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string())
+    } else {
+        Local
+            .timestamp_millis_opt(millis)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+    };
+
+    formatted.unwrap_or_default()
+}
+
+/// Grid lines on round numbers of seconds, minutes and hours.
+pub fn time_grid_spacer(input: GridInput) -> Vec<GridMark> {
+    let next_step = |min: f64| {
+        TIME_STEPS
+            .iter()
+            .copied()
+            .find(|step| *step >= min)
+            .unwrap_or_else(|| (min / 86400.0).ceil() * 86400.0)
+    };
+
+    let small = next_step(input.base_step_size);
+    let medium = next_step(small * 4.0);
+    let large = next_step(medium * 4.0);
+
+    let (min, max) = input.bounds;
+    let mut marks = vec![];
+    for step in [small, medium, large] {
+        let first = (min / step).ceil() as i64;
+        let last = (max / step).floor() as i64;
+        marks.extend((first..=last).map(|i| GridMark {
+            value: i as f64 * step,
+            step_size: step,
+        }));
+    }
+
+    marks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zooming_out_is_limited() {
+        let mut bounds = Bounds {
+            min_x: 1_700_000_000.0,
+            max_x: 1_700_000_600.0,
+            min_y: 0.0,
+            max_y: 70_000.0,
+        };
+        let around = PlotPoint::new(1_700_000_300.0, 35_000.0);
+        for _ in 0..100 {
+            bounds.zoom(egui::vec2(1e-3, 1e-3), around);
+            bounds.limit_span();
+        }
+
+        assert!(bounds.max_x - bounds.min_x <= MAX_SPAN_X);
+        assert!(bounds.max_y - bounds.min_y <= MAX_SPAN_Y);
+        assert!((bounds.min_x / 2.0 + bounds.max_x / 2.0 - around.x).abs() < 1.0);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{data, store};
+use std::time::{Duration, SystemTime};
+
+// How far either side of a point to look for samples of the same trace
+const WINDOW: Duration = Duration::from_secs(30);
+// Height tolerance around the trace, widened by the fastest rate we expect to see
+const BAND_FT: f64 = 600.0;
+const MAX_RATE_FT_PER_SEC: f64 = 10_000.0 / 60.0;
+// Theil-Sen is O(n²) in the number of samples, so larger sets are thinned out
+const MAX_SAMPLES: usize = 256;
+const MIN_SAMPLES: usize = 5;
+// Spacing of the samples when following a trace
+const TRACK_STEP: Duration = Duration::from_secs(5);
+const MAX_TRACK_STEPS: usize = 2_000;
+
+#[derive(Clone, Copy)]
+pub struct Estimate {
+    pub time: SystemTime,
+    pub height: f64,
+    pub feet_per_minute: f64,
+    pub samples: usize,
+}
+
+pub struct Region {
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub min_height: f64,
+    pub max_height: f64,
+}
+
+impl Region {
+    pub fn between(a: (SystemTime, f64), b: (SystemTime, f64)) -> Option<Self> {
+        if a.0 == b.0 {
+            return None;
+        }
+
+        Some(Region {
+            start: a.0.min(b.0),
+            end: a.0.max(b.0),
+            min_height: a.1.min(b.1),
+            max_height: a.1.max(b.1),
+        })
+    }
+}
+
+pub struct RegionRate {
+    pub region: Region,
+    pub estimate: Option<Estimate>,
+}
+
+impl RegionRate {
+    pub fn new(region: Region, points: &[data::Point]) -> Self {
+        let estimate = estimate_region(points, &region);
+        RegionRate { region, estimate }
+    }
+}
+
+#[derive(Default)]
+pub struct Selection {
+    pub tracks: Vec<Vec<Estimate>>,
+    pub region: Option<RegionRate>,
+    pub preview: Option<RegionRate>,
+    pub region_drag_start: Option<(SystemTime, f64)>,
+}
+
+impl Selection {
+    pub fn regions(&self) -> impl Iterator<Item = &RegionRate> {
+        self.region.iter().chain(self.preview.iter())
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.region = None;
+        self.preview = None;
+        self.region_drag_start = None;
+    }
+}
+
+/// Estimate the vertical rate of the trace passing through the given time and height.
+pub fn estimate_at(points: &[data::Point], time: SystemTime, height: f64) -> Option<Estimate> {
+    let samples: Vec<(f64, f64)> = window(points, time - WINDOW, time + WINDOW)
+        .iter()
+        .filter_map(|point| {
+            let dt = seconds_between(time, point.time);
+            let dh = (f64::from(point.height) - height).abs();
+            (dh <= BAND_FT + MAX_RATE_FT_PER_SEC * dt.abs())
+                .then_some((dt, f64::from(point.height)))
+        })
+        .collect();
+
+    let (slope, intercept) = fit(&samples)?;
+
+    // Refit against only the samples that agree with the first pass, this drops other aircraft
+    // that happened to cross through the search cone.
+    let inliers: Vec<(f64, f64)> = samples
+        .into_iter()
+        .filter(|(x, y)| (y - (slope * x + intercept)).abs() <= BAND_FT)
+        .collect();
+    let (slope, intercept) = fit(&inliers)?;
+
+    Some(Estimate {
+        time,
+        height: intercept,
+        feet_per_minute: slope * 60.0,
+        samples: inliers.len(),
+    })
+}
+
+/// Estimate the vertical rate of every point within the region.
+pub fn estimate_region(points: &[data::Point], region: &Region) -> Option<Estimate> {
+    let samples: Vec<(f64, f64)> = window(points, region.start, region.end)
+        .iter()
+        .map(|point| {
+            (
+                seconds_between(region.start, point.time),
+                f64::from(point.height),
+            )
+        })
+        .filter(|(_, height)| *height >= region.min_height && *height <= region.max_height)
+        .collect();
+
+    let (slope, intercept) = fit(&samples)?;
+
+    Some(Estimate {
+        time: region.start,
+        height: intercept,
+        feet_per_minute: slope * 60.0,
+        samples: samples.len(),
+    })
+}
+
+/// Follow a trace forwards and backwards in time from a starting point, estimating the vertical
+/// rate at regular steps along it.
+pub fn follow(points: &[data::Point], time: SystemTime, height: f64) -> Vec<Estimate> {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first.time, last.time),
+        _ => return vec![],
+    };
+
+    let mut backwards = vec![];
+    let mut forwards = vec![];
+
+    for (out, forward) in [(&mut backwards, false), (&mut forwards, true)] {
+        let mut time = time;
+        let mut height = height;
+
+        for _ in 0..MAX_TRACK_STEPS {
+            let estimate = match estimate_at(points, time, height) {
+                Some(estimate) => estimate,
+                None => break,
+            };
+            out.push(estimate);
+
+            let step = TRACK_STEP.as_secs_f64() * if forward { 1.0 } else { -1.0 };
+            height = estimate.height + estimate.feet_per_minute / 60.0 * step;
+            time = match if forward {
+                time.checked_add(TRACK_STEP)
+            } else {
+                time.checked_sub(TRACK_STEP)
+            } {
+                Some(time) if time >= first && time <= last => time,
+                _ => break,
+            };
+        }
+    }
+
+    // Both directions start with the same estimate
+    backwards.reverse();
+    backwards.extend(forwards.into_iter().skip(1));
+    backwards
+}
+
+/// Signed number of seconds from `from` to `to`.
+pub fn seconds_between(from: SystemTime, to: SystemTime) -> f64 {
+    match to.duration_since(from) {
+        Ok(duration) => duration.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+pub fn format_rate(feet_per_minute: f64) -> String {
+    format!("{:+.0} ft/min", feet_per_minute)
+}
+
+fn window(points: &[data::Point], start: SystemTime, end: SystemTime) -> &[data::Point] {
+    &points[store::range(points, start, end)]
+}
+
+// Theil-Sen estimator, returns the slope and intercept of the median line.
+fn fit(samples: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    let stride = (samples.len() + MAX_SAMPLES - 1) / MAX_SAMPLES;
+    let samples: Vec<(f64, f64)> = samples.iter().step_by(stride).copied().collect();
+
+    let mut slopes = vec![];
+    for (i, (x1, y1)) in samples.iter().enumerate() {
+        for (x2, y2) in &samples[i + 1..] {
+            // Replies received at the same instant say nothing about the rate
+            if (x2 - x1).abs() > 0.01 {
+                slopes.push((y2 - y1) / (x2 - x1));
+            }
+        }
+    }
+
+    let slope = median(&mut slopes)?;
+    let mut intercepts: Vec<f64> = samples.iter().map(|(x, y)| y - slope * x).collect();
+    let intercept = median(&mut intercepts)?;
+
+    Some((slope, intercept))
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let middle = values.len() / 2;
+    let (_, median, _) = values.select_nth_unstable_by(middle, f64::total_cmp);
+    Some(*median)
+}